@@ -1,12 +1,20 @@
-use edge_lib::{err, util::Path};
-use sqlx::{Pool, Row, Sqlite};
+use std::pin::Pin;
+
+use edge_lib::{
+    err,
+    util::{data::Fu, mem_table::Edge, Filter, FilterOp, Index, Path, Repeat, Step},
+};
+use sqlx::{Row, SqliteConnection};
 
 mod main {
-    use edge_lib::{err, util::Step};
-    use sqlx::{Pool, Sqlite};
+    use edge_lib::{
+        err,
+        util::{Filter, FilterOp, Index, Repeat, Step},
+    };
+    use sqlx::SqliteConnection;
 
     pub async fn delete_edge_with_source_code(
-        pool: Pool<Sqlite>,
+        conn: &mut SqliteConnection,
         source: &str,
         paper: &str,
         code: &str,
@@ -15,7 +23,7 @@ mod main {
             .bind(source)
             .bind(paper)
             .bind(code)
-            .execute(&pool)
+            .execute(conn)
             .await
             .map_err(|e| {
                 log::error!("{e}\nat delete_edge_with_source_code");
@@ -29,17 +37,100 @@ mod main {
         Ok(())
     }
 
+    pub async fn delete_edge_with_code_target(
+        conn: &mut SqliteConnection,
+        paper: &str,
+        code: &str,
+        target: &str,
+    ) -> err::Result<()> {
+        sqlx::query("delete from edge_t where paper = ? and code = ? and target = ?")
+            .bind(paper)
+            .bind(code)
+            .bind(target)
+            .execute(conn)
+            .await
+            .map_err(|e| {
+                log::error!("{e}\nat delete_edge_with_code_target");
+
+                moon_err::Error::new(
+                    err::ErrorKind::RuntimeError,
+                    e.to_string(),
+                    format!("at delete_edge_with_code_target"),
+                )
+            })?;
+        Ok(())
+    }
+
+    /// Condition of a step, a wildcard step has no code to bind.
+    fn step_cond(step: &Step) -> &'static str {
+        if step.is_wildcard() {
+            "paper=?"
+        } else {
+            "paper=? and code=?"
+        }
+    }
+
+    /// Whether the filter can be a condition of the joins.
+    pub fn can_push_down(filter: &Filter) -> bool {
+        filter
+            .step_v
+            .iter()
+            .all(|step| step.repeat.is_none() && step.index.is_none() && step.filter_v.is_empty())
+    }
+
+    /// Condition on the nodes of `alias`, binds the steps and the value of the filter.
+    fn gen_filter_cond(filter: &Filter, alias: &str) -> String {
+        let mut node = format!("{alias}.root");
+        let mut table_v = Vec::new();
+        let mut cond_v = Vec::new();
+        for (no, step) in filter.step_v.iter().enumerate() {
+            let (next, prev) = if step.arrow == "->" {
+                ("target", "source")
+            } else {
+                ("source", "target")
+            };
+            table_v.push(format!("edge_t f_{no}"));
+            cond_v.push(format!("f_{no}.{prev} = {node}"));
+            cond_v.push(format!("f_{no}.paper = ?"));
+            if !step.is_wildcard() {
+                cond_v.push(format!("f_{no}.code = ?"));
+            }
+            node = format!("f_{no}.{next}");
+        }
+        if table_v.is_empty() {
+            return match &filter.op {
+                FilterOp::Exists => format!("1"),
+                FilterOp::Equal(_) => format!("{node} = ?"),
+                FilterOp::NotEqual(_) => format!("{node} != ?"),
+            };
+        }
+        if let FilterOp::Equal(_) | FilterOp::NotEqual(_) = &filter.op {
+            cond_v.push(format!("{node} = ?"));
+        }
+        let exists = format!(
+            "exists (select 1 from {} where {})",
+            table_v.join(", "),
+            cond_v.join(" and ")
+        );
+        match &filter.op {
+            FilterOp::NotEqual(_) => format!("not {exists}"),
+            _ => exists,
+        }
+    }
+
+    /// Binds the root, the steps, then the filters of the steps.
     pub fn gen_sql_stm(first_step: &Step, step_v: &[Step]) -> String {
+        let cond = step_cond(first_step);
         let sql = if first_step.arrow == "->" {
             format!(
-            "select v_{}.root from (select target as root, id from edge_t where source=? and paper=? and code=?) v_0",
-            step_v.len(),
-       )
+                "select v_{}.root from (select target as root, id from edge_t where source=? and {cond}) v_0",
+                step_v.len(),
+            )
         } else {
             format!(
-            "select v_{}.root from (select source as root, id from edge_t where target=? and paper=? and code=?) v_0",
-            step_v.len(),
-       )
+                "select v_{}.root from (select source as root, id from edge_t where target=? and {cond}) v_0",
+                step_v.len(),
+            )
         };
         let mut root = format!("v_0");
         let mut no = 0;
@@ -47,24 +138,86 @@ mod main {
             let p_root = root.clone();
             no += 1;
             root = format!("v_{no}");
+            let cond = step_cond(step);
             if step.arrow == "->" {
                 format!(
-                    "join (select target as root, source, id from edge_t where paper=? and code=?) v_{no} on v_{no}.source = {p_root}.root",
-               )
+                    "join (select target as root, source, id from edge_t where {cond}) v_{no} on v_{no}.source = {p_root}.root",
+                )
             } else {
                 format!(
-                    "join (select source as root, target, id from edge_t where paper=? and code=?) v_{no} on v_{no}.source = {p_root}.root",
-               )
+                    "join (select source as root, target, id from edge_t where {cond}) v_{no} on v_{no}.target = {p_root}.root",
+                )
             }
         }).reduce(|acc, item| {
             format!("{acc}\n{item}")
         }).unwrap_or_default();
-        format!("{sql}\n{join_v} order by v_{}.id", step_v.len())
+        let filter_cond_v: Vec<String> = std::iter::once(first_step)
+            .chain(step_v)
+            .enumerate()
+            .flat_map(|(no, step)| {
+                step.filter_v
+                    .iter()
+                    .map(move |filter| gen_filter_cond(filter, &format!("v_{no}")))
+            })
+            .collect();
+        let where_s = if filter_cond_v.is_empty() {
+            String::new()
+        } else {
+            format!(" where {}", filter_cond_v.join(" and "))
+        };
+        format!("{sql}\n{join_v}{where_s} order by v_{}.id", step_v.len())
+    }
+
+    /// # Nodes reached by following `step` from `repeat.min` to `repeat.max` times.
+    ///
+    /// Binds the root and the step. Without `max`, a depth over `min` is as good as `min`, so a
+    /// cycle is walked only once.
+    pub fn gen_repeat_sql_stm(step: &Step, repeat: Repeat) -> String {
+        let (next, prev) = if step.arrow == "->" {
+            ("target", "source")
+        } else {
+            ("source", "target")
+        };
+        let cond = step_cond(step);
+        let cap = repeat.max.unwrap_or(std::cmp::max(repeat.min, 1));
+        let max_cond = match repeat.max {
+            Some(max) => format!(" and r_t.depth < {max}"),
+            None => String::new(),
+        };
+        format!(
+            "with recursive r_t(root, depth) as (
+select ?, 0
+union
+select edge_t.{next}, min(r_t.depth + 1, {cap}) from edge_t join r_t on edge_t.{prev} = r_t.root where {cond}{max_cond}
+)
+select root from r_t where depth >= {} group by root order by min(depth)",
+            repeat.min
+        )
+    }
+
+    /// Items at `index` of the items of one node, binds the root and the step.
+    pub fn gen_index_sql_stm(step: &Step, index: Index) -> String {
+        let (next, prev) = if step.arrow == "->" {
+            ("target", "source")
+        } else {
+            ("source", "target")
+        };
+        let cond = step_cond(step);
+        // an index out of the items selects nothing, as `Index::select`
+        let to_i64 = |n: usize| i64::try_from(n).unwrap_or(i64::MAX);
+        let (order, limit, offset) = match index {
+            Index::At(n) if n < 0 => ("desc", 1, -(n + 1)),
+            Index::At(n) => ("asc", 1, n),
+            Index::Range(from, to) => ("asc", to_i64(to.saturating_sub(from)), to_i64(from)),
+        };
+        format!(
+            "select {next} from edge_t where {prev}=? and {cond} order by id {order} limit {limit} offset {offset}"
+        )
     }
 
     #[cfg(test)]
     mod test_gen_sql {
-        use edge_lib::util::Step;
+        use edge_lib::util::{Index, Step};
 
         #[test]
         fn test_gen_sql() {
@@ -73,72 +226,175 @@ mod main {
                     arrow: "->".to_string(),
                     code: "code".to_string(),
                     paper: "".to_string(),
+                    repeat: None,
+                    index: None,
+                    filter_v: vec![],
                 },
                 &vec![Step {
                     arrow: "->".to_string(),
                     code: "code".to_string(),
                     paper: "".to_string(),
+                    repeat: None,
+                    index: None,
+                    filter_v: vec![],
                 }],
             );
             println!("{sql}")
         }
+
+        #[test]
+        fn test_gen_sql_wildcard() {
+            let sql = super::gen_sql_stm(
+                &Step {
+                    arrow: "->".to_string(),
+                    code: "*".to_string(),
+                    paper: "".to_string(),
+                    repeat: None,
+                    index: None,
+                    filter_v: vec![],
+                },
+                &vec![Step {
+                    arrow: "<-".to_string(),
+                    code: "code".to_string(),
+                    paper: "".to_string(),
+                    repeat: None,
+                    index: None,
+                    filter_v: vec![],
+                }],
+            );
+            assert_eq!(sql.matches('?').count(), 4);
+            assert!(sql.contains("v_1.target = v_0.root"));
+        }
+
+        #[test]
+        fn test_gen_sql_filter() {
+            let path = edge_lib::util::Path::from_str(
+                "root->app:user[app:name == 'bob'][<-app:ban->app:*]->app:email",
+            );
+            let sql = super::gen_sql_stm(&path.step_v[0], &path.step_v[1..]);
+            assert_eq!(sql.matches('?').count(), 11);
+            assert!(sql.contains(
+                "exists (select 1 from edge_t f_0, edge_t f_1 where f_0.target = v_0.root"
+            ));
+        }
+
+        #[test]
+        fn test_gen_index_sql() {
+            let step = edge_lib::util::Path::from_str("root->app:item").step_v[0].clone();
+            let sql = super::gen_index_sql_stm(&step, Index::At(i64::MIN));
+            assert!(sql.ends_with(&format!("desc limit 1 offset {}", i64::MAX)));
+            let sql = super::gen_index_sql_stm(&step, Index::Range(5, 2));
+            assert!(sql.ends_with("asc limit 0 offset 5"));
+            let sql = super::gen_index_sql_stm(&step, Index::Range(1, usize::MAX));
+            assert!(sql.ends_with(&format!("asc limit {} offset 1", i64::MAX)));
+        }
     }
 }
 
-pub async fn insert_edge(
-    pool: Pool<Sqlite>,
-    source: &str,
-    paper: &str,
-    code: &str,
-    target_v: &Vec<String>,
-) -> err::Result<()> {
-    if target_v.is_empty() {
+/// Rows of one INSERT, 4 variables each under the default limit of 999.
+const INSERT_CHUNK: usize = 200;
+
+pub async fn insert_edge_v(conn: &mut SqliteConnection, edge_v: &[Edge]) -> err::Result<()> {
+    if edge_v.is_empty() {
         return Ok(());
     }
-    log::info!("commit target_v: {}", target_v.len());
-    let value_v = target_v
-        .iter()
-        .map(|_| format!("(?,?,?,?)"))
-        .reduce(|acc, item| {
-            if acc.is_empty() {
-                item
-            } else {
-                format!("{acc},{item}")
-            }
-        })
-        .unwrap();
+    log::info!("commit edge_v: {}", edge_v.len());
+    for chunk in edge_v.chunks(INSERT_CHUNK) {
+        let value_v = chunk
+            .iter()
+            .map(|_| format!("(?,?,?,?)"))
+            .collect::<Vec<String>>()
+            .join(",");
 
-    let sql = format!("insert into edge_t (source,paper,code,target) values {value_v}");
-    let mut statement = sqlx::query(&sql);
-    for target in target_v {
-        statement = statement.bind(source).bind(paper).bind(code).bind(target);
-    }
-    statement.execute(&pool).await.map_err(|e| {
-        log::error!("{e}\nat insert_edge");
+        let sql = format!("insert into edge_t (source,paper,code,target) values {value_v}");
+        let mut statement = sqlx::query(&sql);
+        for edge in chunk {
+            statement = statement
+                .bind(&edge.source)
+                .bind(&edge.paper)
+                .bind(&edge.code)
+                .bind(&edge.target);
+        }
+        statement.execute(&mut *conn).await.map_err(|e| {
+            log::error!("{e}\nat insert_edge_v");
 
-        moon_err::Error::new(
-            err::ErrorKind::Other(format!("SqlxError")),
-            e.to_string(),
-            format!("at insert_edge"),
-        )
-    })?;
+            moon_err::Error::new(
+                err::ErrorKind::Other(format!("SqlxError")),
+                e.to_string(),
+                format!("at insert_edge_v"),
+            )
+        })?;
+    }
     Ok(())
 }
 
-pub async fn get(pool: Pool<Sqlite>, path: &Path) -> err::Result<Vec<String>> {
-    let first_step = &path.step_v[0];
-    let sql = main::gen_sql_stm(first_step, &path.step_v[1..]);
+async fn get_joined(
+    conn: &mut SqliteConnection,
+    root_v: &[String],
+    step_v: &[Step],
+) -> err::Result<Vec<String>> {
+    let sql = main::gen_sql_stm(&step_v[0], &step_v[1..]);
     let mut arr = Vec::new();
 
-    for root in &path.root_v {
+    for root in root_v {
         let mut stm = sqlx::query(&sql).bind(root);
-        for step in &path.step_v {
-            stm = stm.bind(&step.paper).bind(&step.code);
+        for step in step_v {
+            stm = stm.bind(&step.paper);
+            if !step.is_wildcard() {
+                stm = stm.bind(&step.code);
+            }
         }
-        let rs = stm.fetch_all(&pool).await.map_err(|e| {
+        for filter in step_v.iter().flat_map(|step| &step.filter_v) {
+            for step in &filter.step_v {
+                stm = stm.bind(&step.paper);
+                if !step.is_wildcard() {
+                    stm = stm.bind(&step.code);
+                }
+            }
+            match &filter.op {
+                FilterOp::Exists => (),
+                FilterOp::Equal(value) | FilterOp::NotEqual(value) => stm = stm.bind(value),
+            }
+        }
+        let rs = stm.fetch_all(&mut *conn).await.map_err(|e| {
             log::error!("{e}\n at get");
 
-            moon_err::Error::new(err::ErrorKind::Other(format!("SqlxError")), e.to_string(), format!("at get"))
+            moon_err::Error::new(
+                err::ErrorKind::Other(format!("SqlxError")),
+                e.to_string(),
+                format!("at get"),
+            )
+        })?;
+        for row in rs {
+            arr.push(row.get(0));
+        }
+    }
+
+    Ok(arr)
+}
+
+async fn get_repeated(
+    conn: &mut SqliteConnection,
+    root_v: &[String],
+    step: &Step,
+    repeat: Repeat,
+) -> err::Result<Vec<String>> {
+    let sql = main::gen_repeat_sql_stm(step, repeat);
+    let mut arr = Vec::new();
+
+    for root in root_v {
+        let mut stm = sqlx::query(&sql).bind(root).bind(&step.paper);
+        if !step.is_wildcard() {
+            stm = stm.bind(&step.code);
+        }
+        let rs = stm.fetch_all(&mut *conn).await.map_err(|e| {
+            log::error!("{e}\n at get_repeated");
+
+            moon_err::Error::new(
+                err::ErrorKind::Other(format!("SqlxError")),
+                e.to_string(),
+                format!("at get_repeated"),
+            )
         })?;
         for row in rs {
             arr.push(row.get(0));
@@ -148,29 +404,231 @@ pub async fn get(pool: Pool<Sqlite>, path: &Path) -> err::Result<Vec<String>> {
     Ok(arr)
 }
 
+async fn get_indexed(
+    conn: &mut SqliteConnection,
+    root_v: &[String],
+    step: &Step,
+    index: Index,
+) -> err::Result<Vec<String>> {
+    let sql = main::gen_index_sql_stm(step, index);
+    let mut arr = Vec::new();
+
+    for root in root_v {
+        let mut stm = sqlx::query(&sql).bind(root).bind(&step.paper);
+        if !step.is_wildcard() {
+            stm = stm.bind(&step.code);
+        }
+        let rs = stm.fetch_all(&mut *conn).await.map_err(|e| {
+            log::error!("{e}\n at get_indexed");
+
+            moon_err::Error::new(
+                err::ErrorKind::Other(format!("SqlxError")),
+                e.to_string(),
+                format!("at get_indexed"),
+            )
+        })?;
+        for row in rs {
+            arr.push(row.get(0));
+        }
+    }
+
+    Ok(arr)
+}
+
+/// Keep the nodes whose sub path matches every filter.
+fn filter<'a, 'f>(
+    conn: &'a mut SqliteConnection,
+    node_v: Vec<String>,
+    filter_v: &'a [Filter],
+) -> Pin<Box<dyn Fu<Output = err::Result<Vec<String>>> + 'f>>
+where
+    'a: 'f,
+{
+    Box::pin(async move {
+        let mut node_v = node_v;
+        for filter in filter_v {
+            let mut rs = Vec::with_capacity(node_v.len());
+            for node in node_v {
+                let item_v = get(
+                    &mut *conn,
+                    &Path {
+                        root_v: vec![node.clone()],
+                        step_v: filter.step_v.clone(),
+                    },
+                )
+                .await?;
+                if filter.is_match(&item_v) {
+                    rs.push(node);
+                }
+            }
+            node_v = rs;
+        }
+        Ok(node_v)
+    })
+}
+
+/// # Query the path.
+///
+/// Steps not repeated are joined in one query with their filters as conditions, a repeated or
+/// indexed step or a filter not fitting the conditions is queried alone.
+pub async fn get(conn: &mut SqliteConnection, path: &Path) -> err::Result<Vec<String>> {
+    let is_joinable = |step: &Step| {
+        step.repeat.is_none()
+            && step.index.is_none()
+            && step.filter_v.iter().all(main::can_push_down)
+    };
+    let mut rs = path.root_v.clone();
+    let mut step_v = &path.step_v[..];
+    while !step_v.is_empty() {
+        if is_joinable(&step_v[0]) {
+            let len = step_v
+                .iter()
+                .position(|step| !is_joinable(step))
+                .unwrap_or(step_v.len());
+            rs = get_joined(conn, &rs, &step_v[..len]).await?;
+            step_v = &step_v[len..];
+            continue;
+        }
+
+        let step = Step {
+            index: None,
+            filter_v: vec![],
+            ..step_v[0].clone()
+        };
+        rs = match (step.repeat, step_v[0].index) {
+            (Some(repeat), Some(index)) => {
+                let mut n_rs = Vec::new();
+                for root in &rs {
+                    let item_v =
+                        get_repeated(conn, std::slice::from_ref(root), &step, repeat).await?;
+                    n_rs.extend(index.select(&item_v));
+                }
+                n_rs
+            }
+            (Some(repeat), None) => get_repeated(conn, &rs, &step, repeat).await?,
+            (None, Some(index)) => get_indexed(conn, &rs, &step, index).await?,
+            (None, None) => get_joined(conn, &rs, &[step]).await?,
+        };
+        rs = filter(conn, rs, &step_v[0].filter_v).await?;
+        step_v = &step_v[1..];
+    }
+    Ok(rs)
+}
+
 pub async fn delete_edge_with_source_code(
-    pool: Pool<Sqlite>,
+    conn: &mut SqliteConnection,
+    paper: &str,
+    source: &str,
+    code: &str,
+) -> err::Result<()> {
+    main::delete_edge_with_source_code(conn, source, paper, code).await
+}
+
+pub async fn delete_edge_with_code_target(
+    conn: &mut SqliteConnection,
     paper: &str,
+    code: &str,
+    target: &str,
+) -> err::Result<()> {
+    main::delete_edge_with_code_target(conn, paper, code, target).await
+}
+
+pub async fn delete_edge(
+    conn: &mut SqliteConnection,
     source: &str,
+    paper: &str,
     code: &str,
+    target: &str,
 ) -> err::Result<()> {
-    main::delete_edge_with_source_code(pool, source, paper, code).await
+    sqlx::query("delete from edge_t where source = ? and paper = ? and code = ? and target = ?")
+        .bind(source)
+        .bind(paper)
+        .bind(code)
+        .bind(target)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            log::error!("{e}\nat delete_edge");
+
+            moon_err::Error::new(
+                err::ErrorKind::RuntimeError,
+                e.to_string(),
+                format!("at delete_edge"),
+            )
+        })?;
+    Ok(())
 }
 
-pub async fn get_code_v(pool: Pool<Sqlite>, root: &str, paper: &str) -> err::Result<Vec<String>> {
+/// `(source, paper, code)` of the edges from or to `node`, each once.
+pub async fn get_edge_v_of_node(
+    conn: &mut SqliteConnection,
+    node: &str,
+) -> err::Result<Vec<(String, String, String)>> {
+    Ok(sqlx::query(
+        "select source, paper, code from edge_t where source = ? or target = ? group by source, paper, code order by min(id)",
+    )
+    .bind(node)
+    .bind(node)
+    .fetch_all(conn)
+    .await
+    .map_err(|e| {
+        log::error!("{e}\nat get_edge_v_of_node");
+
+        moon_err::Error::new(
+            err::ErrorKind::Other(format!("SqlxError")),
+            e.to_string(),
+            format!("at get_edge_v_of_node"),
+        )
+    })?
+    .iter()
+    .map(|row| (row.get(0), row.get(1), row.get(2)))
+    .collect())
+}
+
+pub async fn delete_edge_with_node(conn: &mut SqliteConnection, node: &str) -> err::Result<()> {
+    sqlx::query("delete from edge_t where source = ? or target = ?")
+        .bind(node)
+        .bind(node)
+        .execute(conn)
+        .await
+        .map_err(|e| {
+            log::error!("{e}\nat delete_edge_with_node");
+
+            moon_err::Error::new(
+                err::ErrorKind::RuntimeError,
+                e.to_string(),
+                format!("at delete_edge_with_node"),
+            )
+        })?;
+    Ok(())
+}
+
+/// Distinct codes from `root` in `paper`, in inserted order of their first edges.
+pub async fn get_code_v(
+    conn: &mut SqliteConnection,
+    root: &str,
+    paper: &str,
+) -> err::Result<Vec<String>> {
     Ok(
-        sqlx::query("select code from edge_t where source = ? and paper = ?")
-            .bind(root)
-            .bind(paper)
-            .fetch_all(&pool)
-            .await
-            .map_err(|e| {
-                log::error!("{e}\n at get_code_v");
+        // by the index on (source, paper, code)
+        sqlx::query(
+            "select code from edge_t where source = ? and paper = ? group by code order by min(id)",
+        )
+        .bind(root)
+        .bind(paper)
+        .fetch_all(conn)
+        .await
+        .map_err(|e| {
+            log::error!("{e}\n at get_code_v");
 
-                moon_err::Error::new(err::ErrorKind::Other(format!("SqlxError")), e.to_string(), format!("get_code_v"))
-            })?
-            .iter()
-            .map(|row| row.get(0))
-            .collect(),
+            moon_err::Error::new(
+                err::ErrorKind::Other(format!("SqlxError")),
+                e.to_string(),
+                format!("get_code_v"),
+            )
+        })?
+        .iter()
+        .map(|row| row.get(0))
+        .collect(),
     )
 }
@@ -1,11 +1,18 @@
-use sqlx::{sqlite::SqliteConnectOptions, Pool, Sqlite};
-use std::{future, pin::Pin};
+use sqlx::{pool::PoolConnection, sqlite::SqliteConnectOptions, Pool, Sqlite, SqliteConnection};
+use std::{
+    collections::HashSet,
+    future,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+};
+use tokio::sync::{broadcast, Mutex, MutexGuard};
 
 use edge_lib::{
     err,
     util::{
-        data::{AsDataManager, Auth, Fu},
-        Path,
+        data::{AsDataManager, Auth, Change, Fu, WriteOp},
+        mem_table::Edge,
+        Path, Step,
     },
 };
 
@@ -21,27 +28,278 @@ const INIT_SQL: &str = "CREATE TABLE IF NOT EXISTS edge_t (
 CREATE INDEX IF NOT EXISTS edge_t_source_paper_code ON edge_t (source, paper, code);
 CREATE INDEX IF NOT EXISTS edge_t_target_paper_code ON edge_t (target, paper, code);";
 
-#[derive(Clone)]
+/// The open transaction and the changes to notify when committed.
+struct Transaction {
+    tx: sqlx::Transaction<'static, Sqlite>,
+    change_v: Vec<Change>,
+}
+
+/// Connection of the transaction if any, otherwise from the pool.
+enum Conn<'a> {
+    Tx(MutexGuard<'a, Option<Transaction>>),
+    Pool(PoolConnection<Sqlite>),
+}
+
+impl<'a> Deref for Conn<'a> {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Conn::Tx(guard) => &guard.as_ref().unwrap().tx,
+            Conn::Pool(conn) => conn,
+        }
+    }
+}
+
+impl<'a> DerefMut for Conn<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Conn::Tx(guard) => &mut guard.as_mut().unwrap().tx,
+            Conn::Pool(conn) => conn,
+        }
+    }
+}
+
+fn map_sqlx_err(e: sqlx::Error, stack: &str) -> moon_err::Error<err::ErrorKind> {
+    log::error!("{e}\n{stack}");
+
+    moon_err::Error::new(
+        err::ErrorKind::Other(format!("SqlxError")),
+        e.to_string(),
+        stack.to_string(),
+    )
+}
+
+/// Clones share the pool and the notifier of changes, each one has its own transaction.
 pub struct SqliteDataManager {
     pool: Pool<Sqlite>,
     auth: Auth,
+    notifier: broadcast::Sender<Change>,
+    tx: Mutex<Option<Transaction>>,
+}
+
+impl Clone for SqliteDataManager {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            auth: self.auth.clone(),
+            notifier: self.notifier.clone(),
+            tx: Mutex::new(None),
+        }
+    }
 }
 
 impl SqliteDataManager {
     pub fn new(pool: Pool<Sqlite>, auth: Auth) -> Self {
-        Self { pool, auth }
+        Self {
+            pool,
+            auth,
+            notifier: broadcast::channel(64).0,
+            tx: Mutex::new(None),
+        }
     }
 
     pub async fn new_with_file(uri: &str, auth: Auth) -> Self {
         let pool = sqlx::SqlitePool::connect_with(SqliteConnectOptions::new().filename(uri))
             .await
             .unwrap();
-        Self { pool, auth }
+        Self::new(pool, auth)
     }
 
     pub async fn init(&self) {
         sqlx::query(INIT_SQL).execute(&self.pool).await.unwrap();
     }
+
+    async fn conn(&self) -> err::Result<Conn<'_>> {
+        let guard = self.tx.lock().await;
+        if guard.is_some() {
+            return Ok(Conn::Tx(guard));
+        }
+        drop(guard);
+        let conn = self
+            .pool
+            .acquire()
+            .await
+            .map_err(|e| map_sqlx_err(e, "at conn"))?;
+        Ok(Conn::Pool(conn))
+    }
+
+    fn notify(&self, conn: &mut Conn<'_>, source: &str, paper: &str, code: &str) {
+        let change = Change {
+            source: source.to_string(),
+            paper: paper.to_string(),
+            code: code.to_string(),
+        };
+        match conn {
+            Conn::Tx(guard) => guard.as_mut().unwrap().change_v.push(change),
+            Conn::Pool(_) => {
+                // no receiver is fine
+                let _ = self.notifier.send(change);
+            }
+        }
+    }
+
+    /// Notify every source once, for a write through `<-`.
+    fn notify_all(&self, conn: &mut Conn<'_>, source_v: &[String], step: &Step) {
+        let mut source_set = HashSet::new();
+        for source in source_v {
+            if source_set.insert(source) {
+                self.notify(conn, source, &step.paper, &step.code);
+            }
+        }
+    }
+
+    fn check_read(&self, path: &Path) -> err::Result<()> {
+        if let Some(auth) = &self.auth {
+            for step in &path.step_v {
+                if !auth.writer.contains(&step.paper) && !auth.reader.contains(&step.paper) {
+                    return Err(moon_err::Error::new(
+                        err::ErrorKind::PermissionDenied,
+                        format!("{}", step.paper),
+                        format!("at get"),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `sql` by the connection of the transaction.
+    async fn execute_tx(&self, sql: &str) -> err::Result<()> {
+        let mut conn = self.conn().await?;
+        sqlx::query(sql)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| map_sqlx_err(e, "at execute_tx"))?;
+        Ok(())
+    }
+
+    /// Apply `op_v` in order by the connection of the transaction, collecting the edges appended
+    /// until a write or a read needs them in the table.
+    async fn write_op_v(&self, op_v: &[WriteOp]) -> err::Result<()> {
+        let mut conn = self.conn().await?;
+        let mut edge_v = Vec::new();
+        for op in op_v {
+            let mut path = op.path().clone();
+            let step = match path.step_v.pop() {
+                Some(step) => step,
+                None => {
+                    if let WriteOp::DeleteAll(_) = op {
+                        dao::insert_edge_v(&mut conn, &std::mem::take(&mut edge_v)).await?;
+                        self.delete_node_v(&mut conn, &path.root_v).await?;
+                    }
+                    continue;
+                }
+            };
+            let root_v = if path.step_v.is_empty() {
+                path.root_v
+            } else {
+                dao::insert_edge_v(&mut conn, &std::mem::take(&mut edge_v)).await?;
+                self.check_read(&path)?;
+                dao::get(&mut conn, &path).await?
+            };
+            let item_v: &[String] = match op {
+                WriteOp::Set(_, item_v)
+                | WriteOp::Append(_, item_v)
+                | WriteOp::Delete(_, item_v) => item_v,
+                WriteOp::DeleteAll(_) => &[],
+            };
+            let is_back = step.arrow == "<-";
+            let mut source_v = Vec::new();
+            match op {
+                WriteOp::Append(..) => (),
+                WriteOp::Set(..) | WriteOp::DeleteAll(_) => {
+                    dao::insert_edge_v(&mut conn, &std::mem::take(&mut edge_v)).await?;
+                    if is_back {
+                        source_v = dao::get(
+                            &mut conn,
+                            &Path {
+                                root_v: root_v.clone(),
+                                step_v: vec![step.clone()],
+                            },
+                        )
+                        .await?;
+                        for target in &root_v {
+                            dao::delete_edge_with_code_target(
+                                &mut conn,
+                                &step.paper,
+                                &step.code,
+                                target,
+                            )
+                            .await?;
+                        }
+                    } else {
+                        for source in &root_v {
+                            dao::delete_edge_with_source_code(
+                                &mut conn,
+                                &step.paper,
+                                source,
+                                &step.code,
+                            )
+                            .await?;
+                        }
+                    }
+                }
+                WriteOp::Delete(..) => {
+                    dao::insert_edge_v(&mut conn, &std::mem::take(&mut edge_v)).await?;
+                    for node in &root_v {
+                        for item in item_v {
+                            let (source, target) =
+                                if is_back { (item, node) } else { (node, item) };
+                            dao::delete_edge(&mut conn, source, &step.paper, &step.code, target)
+                                .await?;
+                        }
+                    }
+                }
+            }
+            if !matches!(op, WriteOp::Delete(..)) {
+                for node in &root_v {
+                    for item in item_v {
+                        let (source, target) = if is_back { (item, node) } else { (node, item) };
+                        edge_v.push(Edge {
+                            source: source.clone(),
+                            paper: step.paper.clone(),
+                            code: step.code.clone(),
+                            target: target.clone(),
+                        });
+                    }
+                }
+            }
+            if is_back {
+                source_v.extend(item_v.iter().cloned());
+                self.notify_all(&mut conn, &source_v, &step);
+            } else {
+                for source in &root_v {
+                    self.notify(&mut conn, source, &step.paper, &step.code);
+                }
+            }
+        }
+        dao::insert_edge_v(&mut conn, &edge_v).await
+    }
+
+    /// Remove every edge from or to `node_v`.
+    async fn delete_node_v(&self, conn: &mut Conn<'_>, node_v: &[String]) -> err::Result<()> {
+        for node in node_v {
+            let edge_v = dao::get_edge_v_of_node(conn, node).await?;
+            if let Some(auth) = &self.auth {
+                if let Some((_, paper, _)) = edge_v
+                    .iter()
+                    .find(|(_, paper, _)| !auth.writer.contains(paper))
+                {
+                    return Err(moon_err::Error::new(
+                        err::ErrorKind::PermissionDenied,
+                        format!("{paper}"),
+                        format!("at delete_all"),
+                    ));
+                }
+            }
+            dao::delete_edge_with_node(conn, node).await?;
+            for (source, paper, code) in &edge_v {
+                self.notify(conn, source, paper, code);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl AsDataManager for SqliteDataManager {
@@ -58,28 +316,7 @@ impl AsDataManager for SqliteDataManager {
         'a: 'f,
         'a1: 'f,
     {
-        if path.step_v.is_empty() {
-            return Box::pin(future::ready(Ok(())));
-        }
-        Box::pin(async move {
-            let mut path = path.clone();
-            let step = path.step_v.pop().unwrap();
-            if let Some(auth) = &self.auth {
-                if !auth.writer.contains(&step.paper) {
-                    return Err(moon_err::Error::new(
-                        err::ErrorKind::PermissionDenied,
-                        format!("{}", step.paper),
-                        format!("at append"),
-                    ));
-                }
-            }
-            let root_v = self.get(&path).await?;
-            for source in &root_v {
-                dao::insert_edge(self.pool.clone(), source, &step.paper, &step.code, &item_v)
-                    .await?;
-            }
-            Ok(())
-        })
+        self.write_batch(vec![WriteOp::Append(path.clone(), item_v)])
     }
 
     fn set<'a, 'a1, 'f>(
@@ -91,36 +328,89 @@ impl AsDataManager for SqliteDataManager {
         'a: 'f,
         'a1: 'f,
     {
-        if path.step_v.is_empty() {
+        self.write_batch(vec![WriteOp::Set(path.clone(), item_v)])
+    }
+
+    fn delete<'a, 'a1, 'f>(
+        &'a mut self,
+        path: &'a1 Path,
+        item_v: Vec<String>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        self.write_batch(vec![WriteOp::Delete(path.clone(), item_v)])
+    }
+
+    fn delete_all<'a, 'a1, 'f>(
+        &'a mut self,
+        path: &'a1 Path,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        self.write_batch(vec![WriteOp::DeleteAll(path.clone())])
+    }
+
+    /// Every write in one transaction, the current one if begun.
+    fn write_batch<'a, 'f>(
+        &'a mut self,
+        op_v: Vec<WriteOp>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        if op_v
+            .iter()
+            .all(|op| op.path().step_v.is_empty() && !matches!(op, WriteOp::DeleteAll(_)))
+        {
             return Box::pin(future::ready(Ok(())));
         }
         Box::pin(async move {
-            let mut path = path.clone();
-            let step = path.step_v.pop().unwrap();
             if let Some(auth) = &self.auth {
-                if !auth.writer.contains(&step.paper) {
-                    return Err(moon_err::Error::new(
-                        err::ErrorKind::PermissionDenied,
-                        format!("{}", step.paper),
-                        format!("at set"),
-                    ));
+                for op in &op_v {
+                    if let Some(step) = op.path().step_v.last() {
+                        if !auth.writer.contains(&step.paper) {
+                            return Err(moon_err::Error::new(
+                                err::ErrorKind::PermissionDenied,
+                                format!("{}", step.paper),
+                                format!("at write_batch"),
+                            ));
+                        }
+                    }
                 }
             }
-            let root_v = self.get(&path).await?;
-            for source in &root_v {
-                dao::delete_edge_with_source_code(
-                    self.pool.clone(),
-                    &step.paper,
-                    source,
-                    &step.code,
-                )
-                .await?;
+            let is_own = self.tx.lock().await.is_none();
+            if is_own {
+                self.begin().await?;
             }
-            for source in &root_v {
-                dao::insert_edge(self.pool.clone(), source, &step.paper, &step.code, &item_v)
-                    .await?;
+            if !is_own {
+                // undo the batch only, not the transaction it is in
+                self.execute_tx("SAVEPOINT write_batch").await?;
+                let change_cnt = self.tx.lock().await.as_ref().unwrap().change_v.len();
+                let rs = self.write_op_v(&op_v).await;
+                if rs.is_err() {
+                    self.execute_tx("ROLLBACK TO write_batch").await?;
+                    self.tx
+                        .lock()
+                        .await
+                        .as_mut()
+                        .unwrap()
+                        .change_v
+                        .truncate(change_cnt);
+                }
+                self.execute_tx("RELEASE write_batch").await?;
+                return rs;
+            }
+            match self.write_op_v(&op_v).await {
+                Ok(()) => self.commit().await,
+                Err(e) => {
+                    self.rollback().await?;
+                    Err(e)
+                }
             }
-            Ok(())
         })
     }
 
@@ -137,18 +427,8 @@ impl AsDataManager for SqliteDataManager {
         }
         let path = path.clone();
         Box::pin(async move {
-            if let Some(auth) = &self.auth {
-                for step in &path.step_v {
-                    if !auth.writer.contains(&step.paper) && !auth.reader.contains(&step.paper) {
-                        return Err(moon_err::Error::new(
-                            err::ErrorKind::PermissionDenied,
-                            format!("{}", step.paper),
-                            format!("at get"),
-                        ));
-                    }
-                }
-            }
-            dao::get(self.pool.clone(), &path).await
+            self.check_read(&path)?;
+            dao::get(&mut *self.conn().await?, &path).await
         })
     }
 
@@ -162,7 +442,75 @@ impl AsDataManager for SqliteDataManager {
         'a1: 'f,
         'a2: 'f,
     {
-        Box::pin(async move { dao::get_code_v(self.pool.clone(), root, space).await })
+        Box::pin(async move { dao::get_code_v(&mut *self.conn().await?, root, space).await })
+    }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<Change>> {
+        Some(self.notifier.subscribe())
+    }
+
+    fn begin<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        Box::pin(async move {
+            let mut guard = self.tx.lock().await;
+            if guard.is_some() {
+                return Err(moon_err::Error::new(
+                    err::ErrorKind::RuntimeError,
+                    format!("transaction already begun"),
+                    format!("at begin"),
+                ));
+            }
+            let tx = self
+                .pool
+                .begin()
+                .await
+                .map_err(|e| map_sqlx_err(e, "at begin"))?;
+            *guard = Some(Transaction {
+                tx,
+                change_v: Vec::new(),
+            });
+            Ok(())
+        })
+    }
+
+    fn commit<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        Box::pin(async move {
+            let tx = self.tx.lock().await.take().ok_or(moon_err::Error::new(
+                err::ErrorKind::RuntimeError,
+                format!("no transaction"),
+                format!("at commit"),
+            ))?;
+            tx.tx
+                .commit()
+                .await
+                .map_err(|e| map_sqlx_err(e, "at commit"))?;
+            for change in tx.change_v {
+                let _ = self.notifier.send(change);
+            }
+            Ok(())
+        })
+    }
+
+    fn rollback<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        Box::pin(async move {
+            let tx = self.tx.lock().await.take().ok_or(moon_err::Error::new(
+                err::ErrorKind::RuntimeError,
+                format!("no transaction"),
+                format!("at rollback"),
+            ))?;
+            tx.tx
+                .rollback()
+                .await
+                .map_err(|e| map_sqlx_err(e, "at rollback"))
+        })
     }
 }
 
@@ -173,30 +521,520 @@ mod tests {
         engine::{AsEdgeEngine, EdgeEngine},
         Path,
     };
-    use sqlx::sqlite::SqliteConnectOptions;
 
     use crate::SqliteDataManager;
 
-    #[test]
-    fn test_root_type() {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .unwrap();
-        rt.block_on(async {
-            let pool =
-                sqlx::SqlitePool::connect_with(SqliteConnectOptions::new().filename("test.db"))
-                    .await
-                    .unwrap();
-            let mut global = SqliteDataManager::new(pool, None);
-            global.init().await;
-            let mut dm = EdgeEngine::new(&mut global);
-            dm.execute_script(&vec!["root->type = user _".to_string()])
+    /// Data manager of the test database, with its tables created.
+    pub(super) async fn new_global() -> SqliteDataManager {
+        let global = SqliteDataManager::new_with_file("test.db", None).await;
+        global.init().await;
+        global
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_root_type() {
+        let mut global = new_global().await;
+        let mut dm = EdgeEngine::new(&mut global);
+        dm.execute_script(&vec!["root->type = user _".to_string()])
+            .await
+            .unwrap();
+
+        let rs = dm.get(&Path::from_str("root->type")).await.unwrap();
+        assert_eq!(rs[0], "user")
+    }
+}
+
+#[cfg(test)]
+mod test_transaction {
+    use edge_lib::util::{
+        data::AsDataManager,
+        engine::{AsEdgeEngine, EdgeEngine},
+        Path,
+    };
+
+    use crate::tests::new_global;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_transactional() {
+        let mut global = new_global().await;
+        let root = edge_lib::util::gen_value();
+
+        let mut engine = EdgeEngine::new(&mut global);
+        engine.set_transactional(true);
+        assert!(engine
+            .execute_script(&vec![
+                format!("{root}->test:a = 1 _"),
+                format!("$->$:output new abc 1"),
+            ])
+            .await
+            .is_err());
+        assert!(engine
+            .get(&Path::from_str(&format!("{root}->test:a")))
+            .await
+            .unwrap()
+            .is_empty());
+
+        engine
+            .execute_script(&vec![format!("{root}->test:a = 1 _")])
+            .await
+            .unwrap();
+        let rs = engine
+            .get(&Path::from_str(&format!("{root}->test:a")))
+            .await
+            .unwrap();
+        assert_eq!(rs, ["1"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_transaction_per_clone() {
+        let mut global = new_global().await;
+        let mut other = global.clone();
+        let mut receiver = global.subscribe().unwrap();
+        let root = edge_lib::util::gen_value();
+        let a = Path::root(&root).fwd("test", "a");
+        let b = Path::root(&root).fwd("test", "b");
+
+        global.begin().await.unwrap();
+        other.set(&b, vec![format!("1")]).await.unwrap();
+        // notified at once, not when the transaction of `global` is committed
+        assert_eq!(receiver.try_recv().unwrap().code, "b");
+        global.set(&a, vec![format!("1")]).await.unwrap();
+        global.rollback().await.unwrap();
+
+        assert!(other.get(&a).await.unwrap().is_empty());
+        assert_eq!(other.get(&b).await.unwrap(), ["1"]);
+    }
+}
+
+#[cfg(test)]
+mod test_write_batch {
+    use std::collections::HashSet;
+
+    use edge_lib::{
+        err,
+        util::{
+            data::{AsDataManager, PermissionPair, WriteOp},
+            Path,
+        },
+    };
+
+    use crate::{tests::new_global, SqliteDataManager};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_undo_failed_batch() {
+        let mut global = new_global().await;
+        let root = edge_lib::util::gen_value();
+        global
+            .set(&Path::root(&root).fwd("other", "x"), vec![format!("1")])
+            .await
+            .unwrap();
+        let mut dm = SqliteDataManager::new(
+            global.pool.clone(),
+            Some(PermissionPair {
+                writer: HashSet::from([format!("test")]),
+                reader: HashSet::new(),
+            }),
+        );
+        let mut receiver = dm.subscribe().unwrap();
+        let a = Path::root(&root).fwd("test", "a");
+        let b = Path::root(&root).fwd("test", "b");
+        // denied by the edge of `other` from the root
+        let op_v = vec![
+            WriteOp::Append(a.clone(), vec![format!("1")]),
+            WriteOp::DeleteAll(Path::root(&root)),
+        ];
+
+        let e = dm.write_batch(op_v.clone()).await.unwrap_err();
+        assert!(matches!(e.first().0, err::ErrorKind::PermissionDenied));
+        assert!(dm.get(&a).await.unwrap().is_empty());
+        assert!(receiver.try_recv().is_err());
+
+        // in a transaction, only the batch is undone
+        dm.begin().await.unwrap();
+        dm.set(&b, vec![format!("1")]).await.unwrap();
+        dm.write_batch(op_v).await.unwrap_err();
+        dm.commit().await.unwrap();
+        assert!(dm.get(&a).await.unwrap().is_empty());
+        assert_eq!(dm.get(&b).await.unwrap(), ["1"]);
+        assert_eq!(receiver.try_recv().unwrap().code, "b");
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_write_batch() {
+        let mut global = new_global().await;
+        let root = edge_lib::util::gen_value();
+        // more than one INSERT of rows
+        let item_v: Vec<String> = (0..450).map(|_| edge_lib::util::gen_value()).collect();
+        let path = Path::root(&root).fwd("list", "item");
+        global
+            .write_batch(vec![
+                WriteOp::Append(path.clone(), item_v.clone()),
+                WriteOp::Set(path.clone().fwd("list", "name"), vec![format!("a")]),
+                WriteOp::Delete(path.clone(), vec![item_v[0].clone()]),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(global.get(&path).await.unwrap(), item_v[1..]);
+        let rs = global.get(&path.clone().fwd("list", "name")).await.unwrap();
+        assert_eq!(rs, vec![format!("a"); 449]);
+    }
+}
+
+#[cfg(test)]
+mod test_wait {
+    use edge_lib::util::{
+        data::AsDataManager,
+        engine::{AsEdgeEngine, EdgeEngine},
+        Path,
+    };
+
+    use crate::tests::new_global;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_wait() {
+        let mut global = new_global().await;
+        let flag = format!("{}->test:flag", edge_lib::util::gen_value());
+
+        let mut writer = global.clone();
+        let path = Path::from_str(&flag);
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            writer.set(&path, vec!["1".to_string()]).await.unwrap();
+        });
+
+        let mut engine = EdgeEngine::new(&mut global);
+        let rs = engine
+            .execute_script(&vec![format!("$->$:output while1 {flag} 5000")])
+            .await
+            .unwrap();
+        assert!(rs.is_empty());
+        task.await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_wait_filtered() {
+        let mut global = new_global().await;
+        let root = edge_lib::util::gen_value();
+        let user = edge_lib::util::gen_value();
+        global
+            .set(&Path::root(&root).fwd("app", "user"), vec![user.clone()])
+            .await
+            .unwrap();
+
+        // the write is on `user`, not on `root`
+        let mut writer = global.clone();
+        let path = Path::root(&user).fwd("app", "active");
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            writer.set(&path, vec!["1".to_string()]).await.unwrap();
+        });
+
+        let mut engine = EdgeEngine::new(&mut global);
+        let rs = engine
+            .execute_script(&vec![format!(
+                "$->$:output while1 {root}->app:user[app:active] 5000"
+            )])
+            .await
+            .unwrap();
+        assert!(rs.is_empty());
+        task.await.unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_wildcard {
+    use edge_lib::util::{data::AsDataManager, Path};
+
+    use crate::tests::new_global;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_wildcard() {
+        let mut global = new_global().await;
+        let root = edge_lib::util::gen_value();
+        let target = edge_lib::util::gen_value();
+
+        global
+            .set(
+                &Path::from_str(&format!("{root}->test:a")),
+                vec![target.clone()],
+            )
+            .await
+            .unwrap();
+        global
+            .set(
+                &Path::from_str(&format!("{root}->test:b")),
+                vec![format!("b")],
+            )
+            .await
+            .unwrap();
+
+        let rs = global
+            .get(&Path::from_str(&format!("{root}->test:*")))
+            .await
+            .unwrap();
+        assert_eq!(rs, vec![target.clone(), format!("b")]);
+        let rs = global
+            .get(&Path::from_str(&format!("{target}<-test:*")))
+            .await
+            .unwrap();
+        assert_eq!(rs, vec![root]);
+    }
+}
+
+#[cfg(test)]
+mod test_repeat {
+    use edge_lib::util::{data::AsDataManager, Path};
+
+    use crate::tests::new_global;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_repeat() {
+        let mut global = new_global().await;
+        let node_v: Vec<String> = (0..4).map(|_| edge_lib::util::gen_value()).collect();
+        // 0 -> 1 -> 2 -> 0, 1 -> 3
+        for (source, target) in [(0, 1), (1, 2), (2, 0), (1, 3)] {
+            global
+                .append(
+                    &Path::from_str(&format!("{}->tree:child", node_v[source])),
+                    vec![node_v[target].clone()],
+                )
                 .await
                 .unwrap();
+        }
 
-            let rs = dm.get(&Path::from_str("root->type")).await.unwrap();
-            assert_eq!(rs[0], "user")
-        })
+        let mut rs = global
+            .get(&Path::from_str(&format!("{}->tree:child*", node_v[0])))
+            .await
+            .unwrap();
+        rs.sort();
+        let mut expected = node_v.clone();
+        expected.sort();
+        assert_eq!(rs, expected);
+
+        let rs = global
+            .get(&Path::from_str(&format!(
+                "{}->tree:child{{0,1}}->tree:child",
+                node_v[0]
+            )))
+            .await
+            .unwrap();
+        assert_eq!(
+            rs,
+            vec![node_v[1].clone(), node_v[2].clone(), node_v[3].clone()]
+        );
+
+        let mut rs = global
+            .get(&Path::from_str(&format!("{}<-tree:child{{2,}}", node_v[3])))
+            .await
+            .unwrap();
+        rs.sort();
+        let mut expected = node_v[0..3].to_vec();
+        expected.sort();
+        assert_eq!(rs, expected);
+    }
+}
+
+#[cfg(test)]
+mod test_filter {
+    use edge_lib::util::{data::AsDataManager, Path};
+
+    use crate::tests::new_global;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_filter() {
+        let mut global = new_global().await;
+        let root = edge_lib::util::gen_value();
+        let user_v: Vec<String> = (0..3).map(|_| edge_lib::util::gen_value()).collect();
+        global
+            .set(
+                &Path::from_str(&format!("{root}->app:user")),
+                user_v.clone(),
+            )
+            .await
+            .unwrap();
+        for (user, name) in user_v.iter().zip(["bob", "amy", "bob"]) {
+            global
+                .set(
+                    &Path::from_str(&format!("{user}->app:name")),
+                    vec![name.to_string()],
+                )
+                .await
+                .unwrap();
+            global
+                .set(
+                    &Path::from_str(&format!("{user}->app:email")),
+                    vec![format!("{user}@mail")],
+                )
+                .await
+                .unwrap();
+        }
+        global
+            .set(
+                &Path::from_str(&format!("{}->app:ban", user_v[2])),
+                vec![format!("1")],
+            )
+            .await
+            .unwrap();
+
+        let rs = global
+            .get(&Path::from_str(&format!(
+                "{root}->app:user[app:name == 'bob'][app:ban != '1']->app:email"
+            )))
+            .await
+            .unwrap();
+        assert_eq!(rs, vec![format!("{}@mail", user_v[0])]);
+        // not pushed down
+        let rs = global
+            .get(&Path::from_str(&format!(
+                "{root}->app:user[app:name{{1}} == 'amy']"
+            )))
+            .await
+            .unwrap();
+        assert_eq!(rs, vec![user_v[1].clone()]);
+    }
+}
+
+#[cfg(test)]
+mod test_index {
+    use edge_lib::util::{data::AsDataManager, Path};
+
+    use crate::tests::new_global;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_index() {
+        let mut global = new_global().await;
+        let root = edge_lib::util::gen_value();
+        let item_v: Vec<String> = (0..4).map(|_| edge_lib::util::gen_value()).collect();
+        global
+            .set(&Path::root(&root).fwd("list", "item"), item_v.clone())
+            .await
+            .unwrap();
+
+        let rs = global
+            .get(&Path::from_str(&format!("{root}->list:item#0")))
+            .await
+            .unwrap();
+        assert_eq!(rs, vec![item_v[0].clone()]);
+        let rs = global
+            .get(&Path::from_str(&format!("{root}->list:item#-1")))
+            .await
+            .unwrap();
+        assert_eq!(rs, vec![item_v[3].clone()]);
+        let rs = global
+            .get(&Path::from_str(&format!("{root}->list:item#1..3")))
+            .await
+            .unwrap();
+        assert_eq!(rs, item_v[1..3].to_vec());
+        let rs = global
+            .get(&Path::from_str(&format!("{}<-list:item#0", item_v[2])))
+            .await
+            .unwrap();
+        assert_eq!(rs, vec![root.clone()]);
+    }
+}
+
+#[cfg(test)]
+mod test_delete {
+    use edge_lib::util::{data::AsDataManager, Path};
+
+    use crate::tests::new_global;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_delete() {
+        let mut global = new_global().await;
+        let root = edge_lib::util::gen_value();
+        let item_v: Vec<String> = (0..3).map(|_| edge_lib::util::gen_value()).collect();
+        let path = Path::root(&root).fwd("list", "item");
+        global.set(&path, item_v.clone()).await.unwrap();
+
+        global.delete(&path, vec![item_v[1].clone()]).await.unwrap();
+        let rs = global.get(&path).await.unwrap();
+        assert_eq!(rs, vec![item_v[0].clone(), item_v[2].clone()]);
+
+        global.delete_all(&Path::root(&item_v[0])).await.unwrap();
+        let rs = global.get(&path).await.unwrap();
+        assert_eq!(rs, vec![item_v[2].clone()]);
+
+        global.delete_all(&path).await.unwrap();
+        assert!(global.get(&path).await.unwrap().is_empty());
+    }
+}
+
+#[cfg(test)]
+mod test_set_back {
+    use edge_lib::util::{data::AsDataManager, Path};
+
+    use crate::tests::new_global;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_set_back() {
+        let mut global = new_global().await;
+        let child = edge_lib::util::gen_value();
+        let parent_v = vec![edge_lib::util::gen_value(), edge_lib::util::gen_value()];
+
+        let mut receiver = global.subscribe().unwrap();
+        global
+            .append(&Path::root(&child).back("test", "child"), parent_v.clone())
+            .await
+            .unwrap();
+        for parent in &parent_v {
+            let rs = global
+                .get(&Path::root(parent).fwd("test", "child"))
+                .await
+                .unwrap();
+            assert_eq!(rs, vec![child.clone()]);
+        }
+
+        global
+            .set(
+                &Path::root(&child).back("test", "child"),
+                vec![format!("p")],
+            )
+            .await
+            .unwrap();
+        let rs = global
+            .get(&Path::root(&child).back("test", "child"))
+            .await
+            .unwrap();
+        assert_eq!(rs, vec![format!("p")]);
+        let rs = global
+            .get(&Path::root(&parent_v[0]).fwd("test", "child"))
+            .await
+            .unwrap();
+        assert!(rs.is_empty());
+
+        let mut source_v = Vec::new();
+        while let Ok(change) = receiver.try_recv() {
+            source_v.push(change.source);
+        }
+        assert_eq!(source_v.len(), 5);
+    }
+}
+
+#[cfg(test)]
+mod test_code_v {
+    use edge_lib::util::{data::AsDataManager, Path};
+
+    use crate::tests::new_global;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_code_v() {
+        let mut global = new_global().await;
+        let root = edge_lib::util::gen_value();
+        for code in ["b", "a", "b", "c"] {
+            global
+                .append(&Path::root(&root).fwd("test", code), vec![format!("1")])
+                .await
+                .unwrap();
+        }
+        global
+            .set(&Path::root(&root).fwd("test", "c"), vec![format!("2")])
+            .await
+            .unwrap();
+
+        let rs = global.get_code_v(&root, "test").await.unwrap();
+        assert_eq!(rs, vec![format!("b"), format!("a"), format!("c")]);
     }
 }
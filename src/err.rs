@@ -11,6 +11,8 @@ pub enum ErrorKind {
     SyntaxError,
     /// RuntimeError
     RuntimeError,
+    /// Execution stopped by a limit or a cancellation.
+    Interrupted,
 }
 
 pub type Result<T> = std::result::Result<T, moon_err::Error<ErrorKind>>;
@@ -1,4 +1,12 @@
 //! # [edge_lib]
+//!
+//! ## Breaking changes
+//!
+//! Scripts read every path strictly by [util::Path::parse]. In a step, `#n`, `#start..end`,
+//! `{m,n}` and a trailing `*` now index or repeat it and `[` starts a filter, so a script or a
+//! stored path using them as plain characters of a paper or a code fails or reads another step.
+//! Escape such a character by `\` to keep the step plain, `root->list:item\#1` is the code
+//! `item#1`, as [util::Path::to_string] writes it.
 
 pub mod err;
 pub mod util;
@@ -1,7 +1,7 @@
 mod main {
     use crate::util;
 
-    use super::{Path, PathPart, PathType, Step};
+    use super::{Filter, FilterOp, Index, Path, PathPart, PathType, Repeat, Step};
 
     pub fn fmt(this: &Path, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", to_string(this))
@@ -35,40 +35,410 @@ mod main {
             .split(',')
             .map(|root| util::escape_word(root))
             .collect();
-        let mut tail = &path[s..];
+        let step_v = parse_step_v(&path[s..]);
+        Path { root_v, step_v }
+    }
+
+    /// Like `from_str`, but the first syntax error is returned with its byte position.
+    pub fn parse(path: &str) -> Result<Path, (usize, String)> {
+        if path == "_" {
+            return Ok(from_str(path));
+        }
+        if path.is_empty() {
+            return Err((0, format!("empty path")));
+        }
+        check_word(path)?;
+
+        let s = find_arrrow(path).unwrap_or(path.len());
+        let mut start = 0;
+        for root in path[0..s].split(',') {
+            if root.is_empty() {
+                return Err((start, format!("expected a root")));
+            }
+            start += root.len() + 1;
+        }
+        check_step_v(&path[s..], s)?;
+        Ok(from_str(path))
+    }
+
+    /// Quotations, escapes and brackets are balanced.
+    fn check_word(path: &str) -> Result<(), (usize, String)> {
+        let mut quote = None;
+        let mut escape = None;
+        let mut bracket_v = Vec::new();
+        for (pos, ch) in path.char_indices() {
+            if escape.take().is_some() {
+                continue;
+            }
+            match ch {
+                '\\' => escape = Some(pos),
+                '\'' if quote.is_some() => quote = None,
+                '\'' => quote = Some(pos),
+                '[' if quote.is_none() => bracket_v.push(pos),
+                // a matched bracket is popped by the guard
+                ']' if quote.is_none() && bracket_v.pop().is_none() => {
+                    return Err((pos, format!("unexpected ]")));
+                }
+                _ => (),
+            }
+        }
+        if let Some(pos) = escape {
+            return Err((pos, format!("trailing backslash")));
+        }
+        if let Some(pos) = quote {
+            return Err((pos, format!("unterminated quotation")));
+        }
+        if let Some(pos) = bracket_v.pop() {
+            return Err((pos, format!("unclosed bracket")));
+        }
+        Ok(())
+    }
+
+    /// `tail` starts with an arrow or is empty, `offset` is its position in the path.
+    fn check_step_v(mut tail: &str, mut offset: usize) -> Result<(), (usize, String)> {
+        while !tail.is_empty() {
+            let s = match find_arrrow(&tail[2..]) {
+                Some(s) => s + 2,
+                None => tail.len(),
+            };
+            let mut text = &tail[2..s];
+            if text.is_empty() {
+                return Err((offset, format!("expected paper:code after {}", &tail[0..2])));
+            }
+            if let Some(pos) = find_top(text, &["["]) {
+                check_filter_v(&text[pos..], offset + 2 + pos)?;
+                text = &text[0..pos];
+            }
+            // a step without paper is in the empty paper
+            let start = match find_top_char(text, ':') {
+                Some(pos) => pos + 1,
+                None => 0,
+            };
+            let code = &text[start..];
+            if let Some(pos) = find_top_char(code, ':') {
+                return Err((offset + 2 + start + pos, format!("unexpected :")));
+            }
+            if code.is_empty() {
+                return Err((offset + 2 + start, format!("expected code")));
+            }
+            let code = match rfind_top_char(code, '#') {
+                Some(pos) if pos > 0 => {
+                    if parse_index(code.to_string()).1.is_none() {
+                        return Err((offset + 2 + start + pos, format!("invalid index")));
+                    }
+                    &code[0..pos]
+                }
+                _ => code,
+            };
+            if ends_with_top_char(code, '}') && parse_repeat(code.to_string()).1.is_none() {
+                let pos = start + rfind_top_char(code, '{').unwrap_or(0);
+                return Err((offset + 2 + pos, format!("invalid repetition")));
+            }
+            offset += s;
+            tail = &tail[s..];
+        }
+        Ok(())
+    }
+
+    fn check_filter_v(mut text: &str, mut offset: usize) -> Result<(), (usize, String)> {
+        while !text.is_empty() {
+            if !text.starts_with('[') {
+                return Err((offset, format!("expected [ after a filter")));
+            }
+            let end = match find_top(&text[1..], &["]"]) {
+                Some(end) => end + 1,
+                None => return Err((offset, format!("unclosed bracket"))),
+            };
+            let content = &text[1..end];
+            let sub_path = match find_top(content, &["==", "!="]) {
+                Some(pos) => {
+                    if content[pos + 2..].trim().is_empty() {
+                        return Err((offset + 1 + pos, format!("expected a value")));
+                    }
+                    &content[0..pos]
+                }
+                None => content,
+            };
+            let start = offset + 1 + sub_path.len() - sub_path.trim_start().len();
+            let sub_path = sub_path.trim();
+            if find_arrrow(sub_path) == Some(0) {
+                check_step_v(sub_path, start)?;
+            } else if sub_path.is_empty() {
+                if content.trim().is_empty() {
+                    return Err((offset, format!("empty filter")));
+                }
+            } else {
+                check_step_v(&format!("->{sub_path}"), start - 2)?;
+            }
+            offset += end + 1;
+            text = &text[end + 1..];
+        }
+        Ok(())
+    }
+
+    /// `tail` starts with an arrow or is empty.
+    fn parse_step_v(mut tail: &str) -> Vec<Step> {
         let mut step_v = Vec::new();
         while !tail.is_empty() {
             let s = match find_arrrow(&tail[2..]) {
                 Some(s) => s + 2,
                 None => tail.len(),
             };
-            let (paper, code) = {
-                let pair = tail[2..s].split(':').collect::<Vec<&str>>();
-                if pair.len() >= 2 {
-                    (pair[0].to_string(), pair[1].to_string())
-                } else if pair.len() == 1 {
-                    (String::new(), pair[0].to_string())
-                } else {
-                    (String::new(), String::new())
+            let mut text = &tail[2..s];
+            let mut filter_v = Vec::new();
+            if let Some(pos) = find_top(text, &["["]) {
+                if let Some(v) = parse_filter_v(&text[pos..]) {
+                    filter_v = v;
+                    text = &text[0..pos];
                 }
+            }
+            let (paper, code) = match find_top_char(text, ':') {
+                Some(pos) => {
+                    let code = &text[pos + 1..];
+                    // a second `:` ends the code
+                    let end = find_top_char(code, ':').unwrap_or(code.len());
+                    (&text[0..pos], &code[0..end])
+                }
+                None => ("", text),
             };
+            let (code, index) = parse_index(code.to_string());
+            let (code, repeat) = parse_repeat(code);
             step_v.push(Step {
                 arrow: tail[0..2].to_string(),
-                paper,
-                code,
+                paper: parse_step_word(paper),
+                code: parse_step_word(&code),
+                repeat,
+                index,
+                filter_v,
             });
             tail = &tail[s..];
         }
-        Path { root_v, step_v }
+        step_v
+    }
+
+    /// `[sub_path == 'value'][sub_path != 'value'][sub_path]`, `None` if malformed.
+    fn parse_filter_v(mut text: &str) -> Option<Vec<Filter>> {
+        let mut filter_v = Vec::new();
+        while !text.is_empty() {
+            if !text.starts_with('[') {
+                return None;
+            }
+            let end = 1 + find_top(&text[1..], &["]"])?;
+            let content = &text[1..end];
+            let (sub_path, op) = match find_top(content, &["==", "!="]) {
+                Some(pos) => {
+                    let value = content[pos + 2..].trim();
+                    let value = if value.starts_with('\'') {
+                        util::escape_word(value)
+                    } else {
+                        value.to_string()
+                    };
+                    let op = if &content[pos..pos + 2] == "==" {
+                        FilterOp::Equal(value)
+                    } else {
+                        FilterOp::NotEqual(value)
+                    };
+                    (content[0..pos].trim(), op)
+                }
+                None => (content.trim(), FilterOp::Exists),
+            };
+            let step_v = if sub_path.is_empty() || find_arrrow(sub_path) == Some(0) {
+                parse_step_v(sub_path)
+            } else {
+                parse_step_v(&format!("->{sub_path}"))
+            };
+            filter_v.push(Filter { step_v, op });
+            text = &text[end + 1..];
+        }
+        Some(filter_v)
+    }
+
+    /// Split `code#n`, `code#-n` or `code#start..end`, any other code is not indexed.
+    fn parse_index(code: String) -> (String, Option<Index>) {
+        let start = match rfind_top_char(&code, '#') {
+            Some(start) if start > 0 => start,
+            _ => return (code, None),
+        };
+        let index_s = &code[start + 1..];
+        let index = match index_s.split_once("..") {
+            Some((from, to)) => match (from.parse(), to.parse()) {
+                (Ok(from), Ok(to)) if from <= to => Some(Index::Range(from, to)),
+                _ => None,
+            },
+            None => index_s.parse().ok().map(Index::At),
+        };
+        match index {
+            Some(index) => (code[0..start].to_string(), Some(index)),
+            None => (code, None),
+        }
+    }
+
+    /// Split `code*`, `code{m}`, `code{m,}` or `code{m,n}`, any other code is not repeated.
+    fn parse_repeat(code: String) -> (String, Option<Repeat>) {
+        if code.len() > 1 && ends_with_top_char(&code, '*') {
+            return (
+                code[0..code.len() - 1].to_string(),
+                Some(Repeat { min: 1, max: None }),
+            );
+        }
+        if !ends_with_top_char(&code, '}') {
+            return (code, None);
+        }
+        let start = match rfind_top_char(&code, '{') {
+            Some(start) if start > 0 => start,
+            _ => return (code, None),
+        };
+        let range = &code[start + 1..code.len() - 1];
+        let repeat = match range.split_once(',') {
+            Some((min, "")) => min.parse().ok().map(|min| Repeat { min, max: None }),
+            Some((min, max)) => match (min.parse(), max.parse()) {
+                (Ok(min), Ok(max)) if min <= max => Some(Repeat {
+                    min,
+                    max: Some(max),
+                }),
+                _ => None,
+            },
+            None => range.parse().ok().map(|n| Repeat {
+                min: n,
+                max: Some(n),
+            }),
+        };
+        match repeat {
+            Some(repeat) => (code[0..start].to_string(), Some(repeat)),
+            None => (code, None),
+        }
     }
 
     #[cfg(test)]
     mod test_from_str {
+        use crate::util::{FilterOp, Index, Repeat};
+
         #[test]
         fn should_from_str() {
             let path = super::from_str("51aae06c-65e9-468a-83b5-041fd52b37fc->$:proxy->path");
             assert_eq!(path.step_v.len(), 2);
         }
+
+        #[test]
+        fn should_parse_filter() {
+            let path = super::from_str("root->app:user[app:name == 'bob b'][<-app:ban]->app:email");
+            assert_eq!(path.step_v.len(), 2);
+            let filter_v = &path.step_v[0].filter_v;
+            assert_eq!(filter_v[0].step_v[0].arrow, "->");
+            assert_eq!(filter_v[0].op, FilterOp::Equal("bob b".to_string()));
+            assert_eq!(filter_v[1].step_v[0].code, "ban");
+            assert_eq!(filter_v[1].op, FilterOp::Exists);
+            assert_eq!(
+                path.to_string(),
+                "'root'->app:user[->app:name == 'bob\\sb'][<-app:ban]->app:email"
+            );
+            assert_eq!(super::from_str(&path.to_string()), path);
+        }
+
+        #[test]
+        fn should_parse_repeat() {
+            let path = super::from_str("root->tree:child*->tree:child{2,5}->tree:*{1,}->tree:a{x}");
+            assert_eq!(path.step_v[0].code, "child");
+            assert_eq!(path.step_v[0].repeat, Some(Repeat { min: 1, max: None }));
+            assert_eq!(
+                path.step_v[1].repeat,
+                Some(Repeat {
+                    min: 2,
+                    max: Some(5)
+                })
+            );
+            assert!(path.step_v[2].is_wildcard());
+            assert_eq!(path.step_v[3].code, "a{x}");
+            assert_eq!(path.step_v[3].repeat, None);
+            assert_eq!(
+                path.to_string(),
+                "'root'->tree:child{1,}->tree:child{2,5}->tree:*{1,}->tree:a\\{x\\}"
+            );
+        }
+
+        #[test]
+        fn should_parse_index() {
+            let path = super::from_str("root->list:item#0->list:item#-1->list:item{1,}#2..5");
+            assert_eq!(path.step_v[0].code, "item");
+            assert_eq!(path.step_v[0].index, Some(Index::At(0)));
+            assert_eq!(path.step_v[1].index, Some(Index::At(-1)));
+            assert_eq!(path.step_v[2].index, Some(Index::Range(2, 5)));
+            assert_eq!(path.step_v[2].repeat, Some(Repeat { min: 1, max: None }));
+            assert_eq!(
+                path.to_string(),
+                "'root'->list:item#0->list:item#-1->list:item{1,}#2..5"
+            );
+            assert_eq!(super::from_str("root->list:a#b").step_v[0].code, "a#b");
+            assert_eq!(
+                super::parse("root->list:a#5..2"),
+                Err((12, "invalid index".to_string()))
+            );
+
+            let item_v: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+            assert_eq!(Index::At(-1).select(&item_v), ["c"]);
+            assert!(Index::At(3).select(&item_v).is_empty());
+            assert_eq!(Index::Range(1, 5).select(&item_v), ["b", "c"]);
+        }
+
+        #[test]
+        fn should_build() {
+            let path = crate::util::Path::root("a b")
+                .fwd("app", "user")
+                .back("app", "owner");
+            assert_eq!(path.to_string(), "'a\\sb'->app:user<-app:owner");
+            assert_eq!(super::from_str(&path.to_string()), path);
+            assert_eq!(crate::util::Path::root("it's").to_string(), "'it\\'s'");
+        }
+
+        #[test]
+        fn should_build_any_word() {
+            for word in [
+                "x*", "x#1", "x#0..2", "x{2}", "a[b]", "a:b", "a->b", "a<-b", "a b", "a\\", "'a'",
+                "a==b", "\\*",
+            ] {
+                let path = crate::util::Path::root("root")
+                    .fwd(word, word)
+                    .back("p", word);
+                assert!(path.step_v.iter().all(|step| step.is_plain()));
+                assert_eq!(super::parse(&path.to_string()).unwrap(), path, "{word}");
+            }
+            let step = super::from_str("root->p:x[->p:a\\=\\=b == 'v']")
+                .step_v
+                .remove(0);
+            assert_eq!(step.filter_v[0].step_v[0].code, "a==b");
+            assert_eq!(super::from_str("root->p:\\*").step_v[0].code, "*");
+        }
+
+        #[test]
+        fn should_parse_strictly() {
+            let path = "root->app:user[name == 'bob'][<-app:ban]->email";
+            assert_eq!(super::parse(path).unwrap(), super::from_str(path));
+            assert!(super::parse("_").is_ok());
+
+            for (path, pos, message) in [
+                ("", 0, "empty path"),
+                ("a,,b", 2, "expected a root"),
+                ("root->p:c->", 9, "expected paper:code after ->"),
+                ("root-><-p:c", 4, "expected paper:code after ->"),
+                ("root->p:", 8, "expected code"),
+                ("root->p:c:d", 9, "unexpected :"),
+                ("root->p:c{x}", 9, "invalid repetition"),
+                ("root->p:c[name == 'v", 18, "unterminated quotation"),
+                ("root->p:c[name", 9, "unclosed bracket"),
+                ("root->p:c]", 9, "unexpected ]"),
+                ("root->p:c[]", 9, "empty filter"),
+                ("root->p:c[name ==]", 15, "expected a value"),
+                ("root->p:c[name->]", 14, "expected paper:code after ->"),
+                ("root->p:c[a]b", 12, "expected [ after a filter"),
+                ("root->p:c\\", 9, "trailing backslash"),
+            ] {
+                assert_eq!(
+                    super::parse(path),
+                    Err((pos, message.to_string())),
+                    "{path}"
+                );
+            }
+        }
     }
 
     pub fn to_string(this: &Path) -> String {
@@ -80,7 +450,7 @@ mod main {
                 .reduce(|acc, item| format!("{acc},{item}"))
                 .unwrap();
             for step in &this.step_v {
-                s = format!("{s}{}{}:{}", step.arrow, step.paper, step.code);
+                s = format!("{s}{}", step_2_string(step));
             }
             s
         } else {
@@ -88,6 +458,46 @@ mod main {
         }
     }
 
+    fn step_2_string(step: &Step) -> String {
+        let code = if step.is_wildcard() {
+            step.code.clone()
+        } else {
+            step_word_2_string(&step.code)
+        };
+        let mut s = format!("{}{}:{code}", step.arrow, step_word_2_string(&step.paper));
+        match step.repeat {
+            Some(Repeat {
+                min,
+                max: Some(max),
+            }) => s = format!("{s}{{{min},{max}}}"),
+            Some(Repeat { min, max: None }) => s = format!("{s}{{{min},}}"),
+            None => (),
+        }
+        match step.index {
+            Some(Index::At(n)) => s = format!("{s}#{n}"),
+            Some(Index::Range(from, to)) => s = format!("{s}#{from}..{to}"),
+            None => (),
+        }
+        for filter in &step.filter_v {
+            let sub_path = filter
+                .step_v
+                .iter()
+                .map(step_2_string)
+                .collect::<Vec<String>>()
+                .join("");
+            s = match &filter.op {
+                FilterOp::Exists => format!("{s}[{sub_path}]"),
+                FilterOp::Equal(value) => {
+                    format!("{s}[{sub_path} == {}]", util::unescape_word(value))
+                }
+                FilterOp::NotEqual(value) => {
+                    format!("{s}[{sub_path} != {}]", util::unescape_word(value))
+                }
+            };
+        }
+        s
+    }
+
     pub fn path_type(this: &Path) -> PathType {
         let mut cnt = 0;
         for i in 0..this.step_v.len() {
@@ -142,56 +552,94 @@ mod main {
         }
     }
 
-    fn find_quotation(path: &str) -> Option<usize> {
-        let pos = path.find('\'')?;
-
-        if pos == 0 {
-            return Some(0);
+    /// Chars with a meaning in a path, escaped by `\` in a paper or a code.
+    const STEP_SPECIAL_CHAR_V: [char; 12] =
+        ['\\', '\'', ':', '#', '*', '{', '}', '[', ']', '<', '>', '='];
+
+    /// A paper or a code as it is written in a path, read back by [parse_step_word].
+    fn step_word_2_string(word: &str) -> String {
+        let mut s = String::with_capacity(word.len());
+        for ch in word.chars() {
+            match ch {
+                ' ' => s.push_str("\\s"),
+                '\n' => s.push_str("\\n"),
+                '\t' => s.push_str("\\t"),
+                _ => {
+                    if STEP_SPECIAL_CHAR_V.contains(&ch) {
+                        s.push('\\');
+                    }
+                    s.push(ch);
+                }
+            }
         }
+        s
+    }
 
-        if path[0..pos].ends_with("\\'") {
-            return Some(pos + 1 + find_quotation(&path[pos + 1..])?);
+    /// A paper or a code without its escapes, unlike a root it is never quoted.
+    fn parse_step_word(word: &str) -> String {
+        if !word.contains('\\') {
+            return word.to_string();
         }
-
-        Some(pos)
+        util::escape_word(&format!("'{word}'"))
     }
 
-    fn find_arrrow_in_block(path: &str, pos: usize) -> Option<usize> {
-        match find_arrrow_in_pure(&path[0..pos]) {
-            Some(a_pos) => Some(a_pos),
-            None => {
-                let c_pos = pos + 1 + find_quotation(&path[pos + 1..])?;
-                match find_arrrow(&path[c_pos + 1..]) {
-                    Some(a_pos) => Some(c_pos + 1 + a_pos),
-                    None => None,
-                }
+    /// Position of each char of `word` not escaped by `\`.
+    fn top_char_iter(word: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+        let mut escaped = false;
+        word.char_indices().filter(move |(_, ch)| {
+            if escaped {
+                escaped = false;
+                return false;
             }
-        }
+            escaped = *ch == '\\';
+            !escaped
+        })
     }
 
-    fn find_arrrow_in_pure(path: &str) -> Option<usize> {
-        let p = path.find("->");
-        let q = path.find("<-");
-        if p.is_none() && q.is_none() {
-            None
-        } else {
-            Some(if p.is_some() && q.is_some() {
-                let p = p.unwrap();
-                let q = q.unwrap();
-                std::cmp::min(p, q)
-            } else if p.is_some() {
-                p.unwrap()
-            } else {
-                q.unwrap()
-            })
+    fn find_top_char(word: &str, target: char) -> Option<usize> {
+        top_char_iter(word)
+            .find(|(_, ch)| *ch == target)
+            .map(|(pos, _)| pos)
+    }
+
+    fn rfind_top_char(word: &str, target: char) -> Option<usize> {
+        top_char_iter(word)
+            .filter(|(_, ch)| *ch == target)
+            .last()
+            .map(|(pos, _)| pos)
+    }
+
+    fn ends_with_top_char(word: &str, target: char) -> bool {
+        word.ends_with(target)
+            && rfind_top_char(word, target) == Some(word.len() - target.len_utf8())
+    }
+
+    /// Position of the first pattern out of quotations and brackets.
+    fn find_top(path: &str, pat_v: &[&str]) -> Option<usize> {
+        let mut in_quote = false;
+        let mut escaped = false;
+        let mut depth = 0;
+        for (pos, ch) in path.char_indices() {
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            if !in_quote && depth == 0 && pat_v.iter().any(|pat| path[pos..].starts_with(pat)) {
+                return Some(pos);
+            }
+            match ch {
+                '\\' => escaped = true,
+                '\'' => in_quote = !in_quote,
+                '[' if !in_quote => depth += 1,
+                ']' if !in_quote && depth > 0 => depth -= 1,
+                _ => (),
+            }
         }
+        None
     }
 
     fn find_arrrow(path: &str) -> Option<usize> {
-        if let Some(pos) = find_quotation(path) {
-            return find_arrrow_in_block(path, pos);
-        }
-        find_arrrow_in_pure(path)
+        find_top(path, &["->", "<-"])
     }
 }
 
@@ -232,9 +680,7 @@ where
 
             let paper_code = format!("{space}:{code}");
 
-            let sub_root_v = dm
-                .get(&Path::from_str(&format!("{root}->{paper_code}")))
-                .await?;
+            let sub_root_v = dm.get(&Path::root(root).fwd(space, code)).await?;
 
             for sub_root in &sub_root_v {
                 rj_item_v.push(dump(dm, sub_root, space).await?).unwrap();
@@ -253,25 +699,20 @@ pub fn escape_word(mut word: &str) -> String {
     }
 
     let mut rs = String::new();
-    let mut pos = 0;
-    while pos < word.len() {
-        pos += match word[pos..].find('\\') {
-            Some(offset) => {
-                let ch = &word[pos + offset + 1..pos + offset + 2];
-                let ch = match ch {
-                    "n" => "\n",
-                    "t" => "\t",
-                    "s" => " ",
-                    _ => ch,
-                };
-                rs = format!("{rs}{}{ch}", &word[pos..pos + offset]);
-                offset + 2
-            }
-            None => {
-                rs = format!("{rs}{}", &word[pos..]);
-                break;
-            }
-        };
+    let mut char_v = word.chars();
+    while let Some(ch) = char_v.next() {
+        if ch != '\\' {
+            rs.push(ch);
+            continue;
+        }
+        // a trailing backslash is kept
+        match char_v.next() {
+            Some('n') => rs.push('\n'),
+            Some('t') => rs.push('\t'),
+            Some('s') => rs.push(' '),
+            Some(ch) => rs.push(ch),
+            None => rs.push('\\'),
+        }
     }
     rs
 }
@@ -289,25 +730,172 @@ pub enum PathPart {
     EntireTemp,
 }
 
+/// Repetition of a step, following it from `min` to `max` times.
+///
+/// `code*` is `{1,}`, the transitive closure.
+#[derive(Clone, Copy, Eq, Hash, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Repeat {
+    pub min: usize,
+    /// `None` means unlimited.
+    pub max: Option<usize>,
+}
+
+/// Selection of the items a step reaches from each node, by the order they were written in.
+#[derive(Clone, Copy, Eq, Hash, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Index {
+    /// `#n`, counted from the end if negative.
+    At(i64),
+    /// `#start..end`, `end` excluded.
+    Range(usize, usize),
+}
+
+impl Index {
+    /// Items at this index of the items of one node.
+    pub fn select(&self, item_v: &[String]) -> Vec<String> {
+        match *self {
+            Index::At(n) => {
+                let pos = if n < 0 { item_v.len() as i64 + n } else { n };
+                if pos < 0 || pos >= item_v.len() as i64 {
+                    Vec::new()
+                } else {
+                    vec![item_v[pos as usize].clone()]
+                }
+            }
+            Index::Range(from, to) => {
+                let to = to.min(item_v.len());
+                if from >= to {
+                    Vec::new()
+                } else {
+                    item_v[from..to].to_vec()
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Eq, Hash, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FilterOp {
+    /// `[sub_path]`
+    Exists,
+    /// `[sub_path == 'value']`
+    Equal(String),
+    /// `[sub_path != 'value']`, true if `sub_path` is empty.
+    NotEqual(String),
+}
+
+/// Keep the nodes of a step whose `sub_path` matches, the first step of `sub_path` may omit `->`.
 #[derive(Clone, Eq, Hash, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Filter {
+    /// From the node, empty means the node itself.
+    pub step_v: Vec<Step>,
+    pub op: FilterOp,
+}
+
+impl Filter {
+    /// Whether the items of the sub path of a node match.
+    pub fn is_match(&self, item_v: &[String]) -> bool {
+        match &self.op {
+            FilterOp::Exists => !item_v.is_empty(),
+            FilterOp::Equal(value) => item_v.contains(value),
+            FilterOp::NotEqual(value) => !item_v.contains(value),
+        }
+    }
+}
+
+#[derive(Clone, Eq, Hash, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Step {
     pub arrow: String,
     pub paper: String,
     pub code: String,
+    pub repeat: Option<Repeat>,
+    /// Applied to the items of each node, before the filters.
+    pub index: Option<Index>,
+    pub filter_v: Vec<Filter>,
+}
+
+impl Step {
+    /// Code `*` follows every code in the paper.
+    pub fn is_wildcard(&self) -> bool {
+        self.code == "*"
+    }
+
+    /// Neither a wildcard, repeated, indexed nor filtered, so it can be set.
+    pub fn is_plain(&self) -> bool {
+        !self.is_wildcard()
+            && self.repeat.is_none()
+            && self.index.is_none()
+            && self.filter_v.is_empty()
+    }
 }
 
 /// root->paper:code, root->paper:code, root->paper:code
 #[derive(Clone, Eq, Hash, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Path {
     pub root_v: Vec<String>,
     pub step_v: Vec<Step>,
 }
 
 impl Path {
+    /// Never fails, a malformed path is read as well as possible.
     pub fn from_str(path: &str) -> Self {
         main::from_str(path)
     }
 
+    /// Like `from_str`, but a malformed path is a `SyntaxError` telling the column, counted in
+    /// chars from 1, and the reason.
+    pub fn parse(path: &str) -> err::Result<Self> {
+        Self::try_parse(path).map_err(|(column, message)| {
+            moon_err::Error::new(
+                err::ErrorKind::SyntaxError,
+                format!("column {column}: {message}"),
+                format!("at Path::parse"),
+            )
+        })
+    }
+
+    /// Start a path from one root, the words given to the builder are taken as they are.
+    ///
+    /// [Path::to_string] escapes them, so `Path::root("a b").fwd("app", "x*").back("app", "owner")`
+    /// is `'a\sb'->app:x\*<-app:owner`, which parses back to the same path. Only the code `*` is
+    /// always the wildcard.
+    pub fn root(root: &str) -> Self {
+        Self {
+            root_v: vec![root.to_string()],
+            step_v: Vec::new(),
+        }
+    }
+
+    pub fn fwd(self, paper: &str, code: &str) -> Self {
+        self.step("->", paper, code)
+    }
+
+    pub fn back(self, paper: &str, code: &str) -> Self {
+        self.step("<-", paper, code)
+    }
+
+    fn step(mut self, arrow: &str, paper: &str, code: &str) -> Self {
+        self.step_v.push(Step {
+            arrow: arrow.to_string(),
+            paper: paper.to_string(),
+            code: code.to_string(),
+            repeat: None,
+            index: None,
+            filter_v: Vec::new(),
+        });
+        self
+    }
+
+    /// Like `parse`, with the column of the error apart.
+    pub(crate) fn try_parse(path: &str) -> Result<Self, (usize, String)> {
+        main::parse(path).map_err(|(pos, message)| (path[0..pos].chars().count() + 1, message))
+    }
+
     pub fn to_string(&self) -> String {
         main::to_string(self)
     }
@@ -424,5 +1012,7 @@ mod tests {
     fn test_escape_word() {
         let rs = escape_word("\\wo\\nrd");
         assert_eq!(rs, "wo\nrd");
+        assert_eq!(escape_word("word\\"), "word\\");
+        assert_eq!(escape_word("\\字"), "字");
     }
 }
@@ -4,7 +4,12 @@ use std::{
     pin::Pin,
 };
 
-use crate::{err, util::Path};
+use tokio::sync::broadcast;
+
+use crate::{
+    err,
+    util::{Path, Step},
+};
 
 mod mem;
 
@@ -24,6 +29,34 @@ impl<T: Future + Send> Fu for T {}
 
 pub type Auth = Option<PermissionPair>;
 
+/// The targets of `source->paper:code` were changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub source: String,
+    pub paper: String,
+    pub code: String,
+}
+
+/// One write of [AsDataManager::write_batch].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WriteOp {
+    Set(Path, Vec<String>),
+    Append(Path, Vec<String>),
+    Delete(Path, Vec<String>),
+    DeleteAll(Path),
+}
+
+impl WriteOp {
+    pub fn path(&self) -> &Path {
+        match self {
+            WriteOp::Set(path, _)
+            | WriteOp::Append(path, _)
+            | WriteOp::Delete(path, _)
+            | WriteOp::DeleteAll(path) => path,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PermissionPair {
     pub writer: HashSet<String>,
@@ -33,7 +66,11 @@ pub struct PermissionPair {
 pub trait AsDataManager: Send + Sync {
     fn get_auth(&self) -> &Auth;
 
-    /// Get all targets from `source->code`
+    /// Add `item_v` to the end of `path`.
+    ///
+    /// For a last step `->paper:code`, every item becomes a target of each node before it. For
+    /// `<-paper:code`, every item becomes a source of each node before it, so one write attaches
+    /// a node to many parents. A [Change] is notified for every source written.
     fn append<'a, 'a1, 'f>(
         &'a mut self,
         path: &'a1 Path,
@@ -43,7 +80,10 @@ pub trait AsDataManager: Send + Sync {
         'a: 'f,
         'a1: 'f;
 
-    /// Get all targets from `source->code`
+    /// Like [AsDataManager::append], but replace the edges of `paper:code` first.
+    ///
+    /// For `->paper:code` these are the outgoing edges of each node before the last step, for
+    /// `<-paper:code` the incoming ones, whose old sources are notified too.
     fn set<'a, 'a1, 'f>(
         &'a mut self,
         path: &'a1 Path,
@@ -53,6 +93,29 @@ pub trait AsDataManager: Send + Sync {
         'a: 'f,
         'a1: 'f;
 
+    /// Remove the edges of the last step of `path` to each of `item_v`.
+    ///
+    /// For `->paper:code` these are the edges from each node before the last step to an item,
+    /// for `<-paper:code` the edges from an item. A [Change] is notified for every source.
+    fn delete<'a, 'a1, 'f>(
+        &'a mut self,
+        path: &'a1 Path,
+        item_v: Vec<String>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f;
+
+    /// Like [AsDataManager::delete] with every item, a path without steps removes every edge from
+    /// or to its roots.
+    fn delete_all<'a, 'a1, 'f>(
+        &'a mut self,
+        path: &'a1 Path,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f;
+
     /// Get all targets from `source->code`
     fn get<'a, 'a1, 'f>(
         &'a self,
@@ -62,6 +125,7 @@ pub trait AsDataManager: Send + Sync {
         'a: 'f,
         'a1: 'f;
 
+    /// Distinct codes of the edges from `root` in `space`, in inserted order.
     fn get_code_v<'a, 'a1, 'a2, 'f>(
         &'a self,
         root: &'a1 str,
@@ -72,6 +136,88 @@ pub trait AsDataManager: Send + Sync {
         'a1: 'f,
         'a2: 'f;
 
+    /// # Apply every write in order, each seeing the ones before.
+    ///
+    /// A batch is all-or-nothing: if a write fails, none of the batch is kept. The default runs
+    /// it in a transaction of its own, so it falls short twice: in an open transaction the writes
+    /// before the failed one stay until that is rolled back, and a manager whose
+    /// [AsDataManager::begin] fails with `NotFound` keeps them. Override it to avoid both.
+    ///
+    /// A manager may override it to write in fewer round trips, a write whose path has one step
+    /// needs no read before it.
+    fn write_batch<'a, 'f>(
+        &'a mut self,
+        op_v: Vec<WriteOp>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        Box::pin(async move {
+            let is_own = begin_batch(self).await?;
+            let mut rs = Ok(());
+            for op in op_v {
+                rs = match op {
+                    WriteOp::Set(path, item_v) => self.set(&path, item_v).await,
+                    WriteOp::Append(path, item_v) => self.append(&path, item_v).await,
+                    WriteOp::Delete(path, item_v) => self.delete(&path, item_v).await,
+                    WriteOp::DeleteAll(path) => self.delete_all(&path).await,
+                };
+                if rs.is_err() {
+                    break;
+                }
+            }
+            if is_own {
+                rs = end_batch(self, rs).await;
+            }
+            rs
+        })
+    }
+
+    /// Receive a [Change] after every `set` or `append`, `None` if changes can not be notified.
+    fn subscribe(&self) -> Option<broadcast::Receiver<Change>> {
+        None
+    }
+
+    /// # Start a transaction.
+    ///
+    /// Writes until [AsDataManager::commit] are visible to this manager only, and dropped by
+    /// [AsDataManager::rollback]. Transactions can not be nested, beginning one fails with
+    /// `NotFound` if not supported and with `RuntimeError` if one is open.
+    fn begin<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        Box::pin(future::ready(Err(moon_err::Error::new(
+            err::ErrorKind::NotFound,
+            format!("transaction not supported"),
+            format!("at begin"),
+        ))))
+    }
+
+    /// Apply all writes since [AsDataManager::begin] atomically.
+    fn commit<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        Box::pin(future::ready(Err(moon_err::Error::new(
+            err::ErrorKind::NotFound,
+            format!("transaction not supported"),
+            format!("at commit"),
+        ))))
+    }
+
+    /// Drop all writes since [AsDataManager::begin].
+    fn rollback<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        Box::pin(future::ready(Err(moon_err::Error::new(
+            err::ErrorKind::NotFound,
+            format!("transaction not supported"),
+            format!("at rollback"),
+        ))))
+    }
+
     fn call<'a, 'a1, 'a2, 'a3, 'a4, 'f>(
         &'a mut self,
         output: &'a1 Path,
@@ -134,6 +280,10 @@ pub trait AsDataManager: Send + Sync {
         })
     }
 
+    /// # Append `data` to `addr`.
+    ///
+    /// An object is a new node whose keys are steps to its values, an array appends every
+    /// member. Every write is done by one [AsDataManager::write_batch].
     fn load<'a, 'a1, 'a2, 'f>(
         &'a mut self,
         data: &'a1 json::JsonValue,
@@ -145,36 +295,97 @@ pub trait AsDataManager: Send + Sync {
         'a2: 'f,
     {
         Box::pin(async move {
-            if data.is_null() {
-                return Ok(());
-            }
+            let mut path = addr.clone();
+            let step = path.step_v.pop();
+            let root_v = self.get(&path).await?;
+            let mut op_v = Vec::new();
+            load_op_v(data, &root_v, step.as_ref(), &mut op_v);
+            self.write_batch(op_v).await
+        })
+    }
+}
 
-            if data.is_array() {
-                for item in data.members() {
-                    self.load(item, addr).await?;
-                }
-                return Ok(());
-            }
+/// # Begin the transaction of a batch, `false` if the batch has none of its own.
+///
+/// That is if transactions are not supported or one is open, any other error is returned.
+pub(crate) async fn begin_batch<DM: AsDataManager + ?Sized>(dm: &mut DM) -> err::Result<bool> {
+    match dm.begin().await {
+        Ok(()) => Ok(true),
+        Err(e) => match e.first().0 {
+            err::ErrorKind::NotFound | err::ErrorKind::RuntimeError => Ok(false),
+            _ => Err(e),
+        },
+    }
+}
 
-            if !data.is_object() {
-                self.append(addr, vec![data.as_str().unwrap().to_string()])
-                    .await?;
-                return Ok(());
+/// Commit the transaction of a batch if `rs` is ok, otherwise roll it back and keep `rs`.
+pub(crate) async fn end_batch<DM: AsDataManager + ?Sized>(
+    dm: &mut DM,
+    rs: err::Result<()>,
+) -> err::Result<()> {
+    match rs {
+        Ok(()) => dm.commit().await,
+        Err(e) => {
+            if let Err(rollback_e) = dm.rollback().await {
+                log::error!("{:?}\nat end_batch", rollback_e);
             }
+            Err(e)
+        }
+    }
+}
 
-            self.append(addr, vec![super::gen_value()]).await?;
+/// Writes appending `data` to `root_v` through `step`, an object without `step` is the roots.
+fn load_op_v(
+    data: &json::JsonValue,
+    root_v: &[String],
+    step: Option<&Step>,
+    op_v: &mut Vec<WriteOp>,
+) {
+    if data.is_null() {
+        return;
+    }
 
-            for (k, v) in data.entries() {
-                let sub_path = Path::from_str(&format!("{}->{k}", addr.to_string()));
-                if v.is_array() {
-                    for item in v.members() {
-                        self.load(item, &sub_path).await?;
-                    }
-                } else {
-                    self.load(v, &sub_path).await?;
-                }
-            }
-            Ok(())
-        })
+    if data.is_array() {
+        for item in data.members() {
+            load_op_v(item, root_v, step, op_v);
+        }
+        return;
+    }
+
+    let one_step = |step: &Step| Path {
+        root_v: root_v.to_vec(),
+        step_v: vec![step.clone()],
+    };
+
+    if !data.is_object() {
+        if let Some(step) = step {
+            op_v.push(WriteOp::Append(
+                one_step(step),
+                vec![data.as_str().unwrap().to_string()],
+            ));
+        }
+        return;
+    }
+
+    let node_v = match step {
+        Some(step) => {
+            let node = super::gen_value();
+            op_v.push(WriteOp::Append(one_step(step), vec![node.clone()]));
+            vec![node]
+        }
+        None => root_v.to_vec(),
+    };
+    for (k, v) in data.entries() {
+        // a key is a plain step, even with `*`, `#`, `{` or `[` in it
+        let (paper, code) = k.split_once(':').unwrap_or(("", k));
+        let sub_step = Step {
+            arrow: format!("->"),
+            paper: paper.to_string(),
+            code: code.to_string(),
+            repeat: None,
+            index: None,
+            filter_v: Vec::new(),
+        };
+        load_op_v(v, &node_v, Some(&sub_step), op_v);
     }
 }
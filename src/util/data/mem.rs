@@ -1,11 +1,29 @@
-use std::{future, pin::Pin};
+use std::{
+    cmp::min,
+    collections::{HashSet, VecDeque},
+    fs, future, io,
+    path::PathBuf,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::broadcast;
 
 use crate::{
     err,
-    util::{mem_table, Path},
+    util::{
+        mem_table::{self, TableOp},
+        Path, Repeat, Step,
+    },
 };
 
-use super::{AsDataManager, Auth, Fu};
+use super::{AsDataManager, Auth, Change, Fu, WriteOp};
+
+mod snapshot;
+mod wal;
+
+pub use snapshot::MemSnapshot;
+pub use wal::{AsLogStorage, FileLog};
 
 mod main {
     #[cfg(test)]
@@ -15,59 +33,725 @@ mod main {
             Path,
         };
 
-        #[test]
-        fn should_get_source_v() {
-            tokio::runtime::Builder::new_multi_thread()
-                .enable_all()
-                .build()
-                .unwrap()
-                .block_on(async {
-                    let mut dm = MemDataManager::new(None);
-                    dm.set(&Path::from_str("root->web_server"), vec!["id".to_string()])
-                        .await
-                        .unwrap();
-                    dm.set(&Path::from_str("id->name"), vec!["test".to_string()])
-                        .await
-                        .unwrap();
-                    let test = dm.get(&Path::from_str("test<-name")).await.unwrap();
-                    let test1 = dm.get(&Path::from_str("root->web_server")).await.unwrap();
-                    assert_eq!(test, test1);
-                })
-        }
-
-        #[test]
-        fn should_get_source() {
-            tokio::runtime::Builder::new_multi_thread()
-                .enable_all()
-                .build()
-                .unwrap()
-                .block_on(async {
-                    let mut dm = MemDataManager::new(None);
-                    dm.set(&Path::from_str("root->web_server"), vec!["id".to_string()])
-                        .await
-                        .unwrap();
-                    dm.set(&Path::from_str("id->name"), vec!["test".to_string()])
-                        .await
-                        .unwrap();
-                    dm.set(
-                        &Path::from_str("root->web_server->name"),
-                        vec!["test".to_string()],
-                    )
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_get_source_v() {
+            let mut dm = MemDataManager::new(None);
+            dm.set(&Path::from_str("root->web_server"), vec!["id".to_string()])
+                .await
+                .unwrap();
+            dm.set(&Path::from_str("id->name"), vec!["test".to_string()])
+                .await
+                .unwrap();
+            let test = dm.get(&Path::from_str("test<-name")).await.unwrap();
+            let test1 = dm.get(&Path::from_str("root->web_server")).await.unwrap();
+            assert_eq!(test, test1);
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_get_source() {
+            let mut dm = MemDataManager::new(None);
+            dm.set(&Path::from_str("root->web_server"), vec!["id".to_string()])
+                .await
+                .unwrap();
+            dm.set(&Path::from_str("id->name"), vec!["test".to_string()])
+                .await
+                .unwrap();
+            dm.set(
+                &Path::from_str("root->web_server->name"),
+                vec!["test".to_string()],
+            )
+            .await
+            .unwrap();
+            let web_server = dm
+                .get(&Path::from_str("root->web_server->name"))
+                .await
+                .unwrap();
+            assert_eq!(web_server.len(), 1);
+        }
+    }
+
+    #[cfg(test)]
+    mod test_notify {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_notify_change() {
+            let mut dm = MemDataManager::new(None);
+            let mut receiver = dm.subscribe().unwrap();
+            dm.append(&Path::from_str("root->web_server"), vec!["id".to_string()])
+                .await
+                .unwrap();
+            let change = receiver.recv().await.unwrap();
+            assert_eq!(change.source, "root");
+            assert_eq!(change.code, "web_server");
+        }
+    }
+
+    #[cfg(test)]
+    mod test_transaction {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_rollback() {
+            let mut dm = MemDataManager::new(None);
+            dm.set(&Path::from_str("root->name"), vec!["a".to_string()])
+                .await
+                .unwrap();
+
+            dm.begin().await.unwrap();
+            dm.set(&Path::from_str("root->name"), vec!["b".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(dm.get(&Path::from_str("root->name")).await.unwrap(), ["b"]);
+            dm.rollback().await.unwrap();
+            assert_eq!(dm.get(&Path::from_str("root->name")).await.unwrap(), ["a"]);
+
+            dm.begin().await.unwrap();
+            dm.set(&Path::from_str("root->name"), vec!["c".to_string()])
+                .await
+                .unwrap();
+            dm.commit().await.unwrap();
+            assert_eq!(dm.get(&Path::from_str("root->name")).await.unwrap(), ["c"]);
+        }
+    }
+
+    #[cfg(test)]
+    mod test_wildcard {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_get_wildcard() {
+            let mut dm = MemDataManager::new(None);
+            dm.set(&Path::from_str("root->node:b"), vec!["b".to_string()])
+                .await
+                .unwrap();
+            dm.set(&Path::from_str("root->node:a"), vec!["a".to_string()])
+                .await
+                .unwrap();
+            dm.set(&Path::from_str("root->other:c"), vec!["c".to_string()])
+                .await
+                .unwrap();
+            dm.set(&Path::from_str("root1->node:c"), vec!["a".to_string()])
+                .await
+                .unwrap();
+
+            assert_eq!(
+                dm.get(&Path::from_str("root->node:*")).await.unwrap(),
+                ["b", "a"]
+            );
+            assert_eq!(
+                dm.get(&Path::from_str("a<-node:*")).await.unwrap(),
+                ["root", "root1"]
+            );
+
+            dm.set(&Path::from_str("root->node:a"), vec![])
+                .await
+                .unwrap();
+            assert_eq!(
+                dm.get(&Path::from_str("a<-node:*")).await.unwrap(),
+                ["root1"]
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod test_get_code_v {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_get_code_v() {
+            let mut dm = MemDataManager::new(None);
+            for code in ["c", "a", "c", "b"] {
+                dm.append(&Path::root("root").fwd("node", code), vec!["1".to_string()])
                     .await
                     .unwrap();
-                    let web_server = dm
-                        .get(&Path::from_str("root->web_server->name"))
-                        .await
-                        .unwrap();
-                    assert_eq!(web_server.len(), 1);
-                })
+            }
+            dm.append(&Path::root("root1").fwd("node", "d"), vec!["1".to_string()])
+                .await
+                .unwrap();
+            // the first edge of `c` is gone, so it goes after `b`
+            dm.set(&Path::root("root").fwd("node", "c"), vec!["2".to_string()])
+                .await
+                .unwrap();
+
+            assert_eq!(
+                dm.get_code_v("root", "node").await.unwrap(),
+                ["a", "b", "c"]
+            );
+            assert!(dm.get_code_v("root", "other").await.unwrap().is_empty());
+        }
+    }
+
+    #[cfg(test)]
+    mod test_repeat {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_get_repeat() {
+            let mut dm = MemDataManager::new(None);
+            // a -> b -> c -> a, b -> d
+            for (source, target) in [("a", "b"), ("b", "c"), ("c", "a"), ("b", "d")] {
+                dm.append(
+                    &Path::from_str(&format!("{source}->tree:child")),
+                    vec![target.to_string()],
+                )
+                .await
+                .unwrap();
+            }
+
+            let mut rs = dm.get(&Path::from_str("a->tree:child*")).await.unwrap();
+            rs.sort();
+            assert_eq!(rs, ["a", "b", "c", "d"]);
+            assert_eq!(
+                dm.get(&Path::from_str("a->tree:child{0,1}")).await.unwrap(),
+                ["a", "b"]
+            );
+            let mut rs = dm.get(&Path::from_str("a->tree:child{2}")).await.unwrap();
+            rs.sort();
+            assert_eq!(rs, ["c", "d"]);
+            let mut rs = dm.get(&Path::from_str("d<-tree:child{2,}")).await.unwrap();
+            rs.sort();
+            assert_eq!(rs, ["a", "b", "c"]);
+        }
+    }
+
+    #[cfg(test)]
+    mod test_filter {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_get_filtered() {
+            let mut dm = MemDataManager::new(None);
+            dm.set(
+                &Path::from_str("root->app:user"),
+                vec!["u1".to_string(), "u2".to_string(), "u3".to_string()],
+            )
+            .await
+            .unwrap();
+            for (user, name) in [("u1", "bob"), ("u2", "amy"), ("u3", "bob")] {
+                dm.set(
+                    &Path::from_str(&format!("{user}->app:name")),
+                    vec![name.to_string()],
+                )
+                .await
+                .unwrap();
+                dm.set(
+                    &Path::from_str(&format!("{user}->app:email")),
+                    vec![format!("{user}@mail")],
+                )
+                .await
+                .unwrap();
+            }
+            dm.set(&Path::from_str("u3->app:ban"), vec!["1".to_string()])
+                .await
+                .unwrap();
+
+            assert_eq!(
+                dm.get(&Path::from_str(
+                    "root->app:user[app:name == 'bob'][app:ban != '1']->app:email"
+                ))
+                .await
+                .unwrap(),
+                ["u1@mail"]
+            );
+            assert_eq!(
+                dm.get(&Path::from_str("root->app:user[app:ban]"))
+                    .await
+                    .unwrap(),
+                ["u3"]
+            );
         }
     }
+
+    #[cfg(test)]
+    mod test_index {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_get_indexed() {
+            let mut dm = MemDataManager::new(None);
+            for (list, item_v) in [("l1", ["a", "b", "c"]), ("l2", ["d", "e", "f"])] {
+                dm.set(
+                    &Path::from_str(&format!("{list}->list:item")),
+                    item_v.iter().map(|item| item.to_string()).collect(),
+                )
+                .await
+                .unwrap();
+            }
+
+            assert_eq!(
+                dm.get(&Path::from_str("l1,l2->list:item#0")).await.unwrap(),
+                ["a", "d"]
+            );
+            assert_eq!(
+                dm.get(&Path::from_str("l1->list:item#-1")).await.unwrap(),
+                ["c"]
+            );
+            assert_eq!(
+                dm.get(&Path::from_str("l2->list:item#1..5")).await.unwrap(),
+                ["e", "f"]
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod test_delete {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_delete() {
+            let mut dm = MemDataManager::new(None);
+            dm.set(
+                &Path::from_str("root->list:item"),
+                vec!["a".to_string(), "b".to_string(), "a".to_string()],
+            )
+            .await
+            .unwrap();
+            dm.set(&Path::from_str("b->list:item"), vec!["c".to_string()])
+                .await
+                .unwrap();
+
+            dm.delete(&Path::from_str("root->list:item"), vec!["a".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(
+                dm.get(&Path::from_str("root->list:item")).await.unwrap(),
+                ["b"]
+            );
+
+            dm.delete_all(&Path::from_str("b")).await.unwrap();
+            assert!(dm
+                .get(&Path::from_str("root->list:item"))
+                .await
+                .unwrap()
+                .is_empty());
+            assert!(dm
+                .get(&Path::from_str("c<-list:item"))
+                .await
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[cfg(test)]
+    mod test_set_back {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_set_back() {
+            let mut dm = MemDataManager::new(None);
+            let mut receiver = dm.subscribe().unwrap();
+            dm.append(
+                &Path::from_str("x<-tree:child"),
+                vec!["a".to_string(), "b".to_string()],
+            )
+            .await
+            .unwrap();
+            assert_eq!(
+                dm.get(&Path::from_str("a->tree:child")).await.unwrap(),
+                ["x"]
+            );
+            assert_eq!(
+                dm.get(&Path::from_str("b->tree:child")).await.unwrap(),
+                ["x"]
+            );
+
+            dm.set(&Path::from_str("x<-tree:child"), vec!["c".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(
+                dm.get(&Path::from_str("x<-tree:child")).await.unwrap(),
+                ["c"]
+            );
+            assert!(dm
+                .get(&Path::from_str("a->tree:child"))
+                .await
+                .unwrap()
+                .is_empty());
+
+            let mut source_v = Vec::new();
+            while let Ok(change) = receiver.try_recv() {
+                source_v.push(change.source);
+            }
+            assert_eq!(source_v, ["a", "b", "a", "b", "c"]);
+        }
+    }
+
+    #[cfg(test)]
+    mod test_write_batch {
+        use std::collections::HashSet;
+
+        use crate::{
+            err,
+            util::{
+                data::{AsDataManager, MemDataManager, PermissionPair, WriteOp},
+                Path,
+            },
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_write_batch() {
+            let mut dm = MemDataManager::new(None);
+            let mut receiver = dm.subscribe().unwrap();
+            dm.write_batch(vec![
+                WriteOp::Append(
+                    Path::from_str("root->list:item"),
+                    vec!["a".to_string(), "b".to_string()],
+                ),
+                WriteOp::Set(
+                    Path::from_str("root->list:item->list:name"),
+                    vec!["x".to_string()],
+                ),
+                WriteOp::Delete(Path::from_str("root->list:item"), vec!["b".to_string()]),
+                WriteOp::DeleteAll(Path::from_str("b")),
+            ])
+            .await
+            .unwrap();
+
+            assert_eq!(
+                dm.get(&Path::from_str("root->list:item")).await.unwrap(),
+                ["a"]
+            );
+            assert_eq!(
+                dm.get(&Path::from_str("a->list:name")).await.unwrap(),
+                ["x"]
+            );
+            assert!(dm
+                .get(&Path::from_str("b->list:name"))
+                .await
+                .unwrap()
+                .is_empty());
+
+            let mut source_v = Vec::new();
+            while let Ok(change) = receiver.try_recv() {
+                source_v.push(change.source);
+            }
+            assert_eq!(source_v, ["root", "a", "b", "root", "b"]);
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_undo_failed_batch() {
+            let mut dm = MemDataManager::new(Some(PermissionPair {
+                writer: HashSet::from(["list".to_string()]),
+                reader: HashSet::new(),
+            }));
+            let mut receiver = dm.subscribe().unwrap();
+            let path = Path::from_str("root->list:item");
+            let op_v = vec![
+                WriteOp::Append(path.clone(), vec!["b".to_string()]),
+                WriteOp::Append(Path::from_str("root->other:item"), vec!["c".to_string()]),
+            ];
+
+            let e = dm.write_batch(op_v.clone()).await.unwrap_err();
+            assert!(matches!(e.first().0, err::ErrorKind::PermissionDenied));
+            assert!(dm.get(&path).await.unwrap().is_empty());
+            assert!(receiver.try_recv().is_err());
+
+            // in a transaction, only the batch is undone
+            dm.begin().await.unwrap();
+            dm.append(&path, vec!["a".to_string()]).await.unwrap();
+            dm.write_batch(op_v).await.unwrap_err();
+            dm.commit().await.unwrap();
+            assert_eq!(dm.get(&path).await.unwrap(), ["a"]);
+            assert_eq!(receiver.try_recv().unwrap().source, "root");
+            assert!(receiver.try_recv().is_err());
+        }
+    }
+
+    #[cfg(test)]
+    mod test_open {
+        use crate::util::{
+            data::{AsDataManager, MemDataManager},
+            Path,
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_open() {
+            let file = std::env::temp_dir().join(format!("{}.snapshot", crate::util::gen_value()));
+            let mut dm = MemDataManager::open(&file, None).unwrap();
+            dm.set(
+                &Path::from_str("root->list:item"),
+                vec!["b".to_string(), "a\tb\n\\c".to_string()],
+            )
+            .await
+            .unwrap();
+            dm.checkpoint().unwrap();
+
+            dm.begin().await.unwrap();
+            dm.append(&Path::from_str("root->list:item"), vec!["d".to_string()])
+                .await
+                .unwrap();
+            // not committed
+            dm.checkpoint().unwrap();
+
+            let mut dm = MemDataManager::open(&file, None).unwrap();
+            dm.append(&Path::from_str("root->list:item"), vec!["a".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(
+                dm.get(&Path::from_str("root->list:item")).await.unwrap(),
+                ["b", "a\tb\n\\c", "a"]
+            );
+
+            // an edge of a taken id
+            let mut content = std::fs::read_to_string(&file).unwrap();
+            let edge = content.lines().nth(2).unwrap().to_string();
+            content.push_str(&format!("{edge}\n"));
+            std::fs::write(&file, content).unwrap();
+            assert!(MemDataManager::open(&file, None).is_err());
+            std::fs::remove_file(&file).unwrap();
+        }
+    }
+
+    #[cfg(test)]
+    mod test_log {
+        use std::{
+            io,
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Arc, Mutex,
+            },
+        };
+
+        use crate::util::{
+            data::{AsDataManager, AsLogStorage, FileLog, MemDataManager},
+            Path,
+        };
+
+        /// Log in memory shared by its clones, every write fails while `is_broken`.
+        #[derive(Clone, Default)]
+        struct MemLog {
+            record_v: Arc<Mutex<Vec<String>>>,
+            is_broken: Arc<AtomicBool>,
+        }
+
+        impl MemLog {
+            fn check(&self) -> io::Result<()> {
+                if self.is_broken.load(Ordering::SeqCst) {
+                    return Err(io::Error::other("broken"));
+                }
+                Ok(())
+            }
+        }
+
+        impl AsLogStorage for MemLog {
+            fn read_all(&mut self) -> io::Result<Vec<String>> {
+                Ok(self.record_v.lock().unwrap().clone())
+            }
+
+            fn append(&mut self, record_v: &[String]) -> io::Result<()> {
+                self.check()?;
+                self.record_v.lock().unwrap().extend_from_slice(record_v);
+                Ok(())
+            }
+
+            fn rewrite(&mut self, record_v: &[String]) -> io::Result<()> {
+                self.check()?;
+                *self.record_v.lock().unwrap() = record_v.to_vec();
+                Ok(())
+            }
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_replay_log() {
+            let file = std::env::temp_dir().join(format!("{}.snapshot", crate::util::gen_value()));
+            let log = std::env::temp_dir().join(format!("{}.log", crate::util::gen_value()));
+            let path = Path::from_str("root->list:item");
+            let open = || {
+                MemDataManager::open(&file, None)
+                    .unwrap()
+                    .with_log(FileLog::new(&log))
+                    .unwrap()
+            };
+
+            let mut dm = open();
+            dm.set(&path, vec!["a".to_string(), "b".to_string()])
+                .await
+                .unwrap();
+            dm.checkpoint().unwrap();
+            dm.delete(&path, vec!["a".to_string()]).await.unwrap();
+            dm.begin().await.unwrap();
+            dm.append(&path, vec!["c".to_string()]).await.unwrap();
+            dm.rollback().await.unwrap();
+            dm.append(&path, vec!["d".to_string()]).await.unwrap();
+
+            // no checkpoint after the last writes
+            let mut dm = open();
+            assert_eq!(dm.get(&path).await.unwrap(), ["b", "d"]);
+
+            dm.checkpoint().unwrap();
+            assert_eq!(FileLog::new(&log).read_all().unwrap().len(), 1);
+            let mut dm = open();
+            assert_eq!(dm.get(&path).await.unwrap(), ["b", "d"]);
+
+            // compacted to inserts without a file
+            std::fs::remove_file(&file).unwrap();
+            dm.snapshot_file = None;
+            dm.checkpoint().unwrap();
+            let dm = MemDataManager::new(None)
+                .with_log(FileLog::new(&log))
+                .unwrap();
+            assert_eq!(dm.get(&path).await.unwrap(), ["b", "d"]);
+            std::fs::remove_file(&log).unwrap();
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_drop_log_older_than_snapshot() {
+            let file = std::env::temp_dir().join(format!("{}.snapshot", crate::util::gen_value()));
+            let log = MemLog::default();
+            let path = Path::from_str("root->list:item");
+            let open = || {
+                MemDataManager::open(&file, None)
+                    .unwrap()
+                    .with_log(log.clone())
+                    .unwrap()
+            };
+
+            let mut dm = open();
+            dm.set(&path, vec!["a".to_string(), "b".to_string()])
+                .await
+                .unwrap();
+            dm.checkpoint().unwrap();
+            dm.delete_all(&Path::from_str("a")).await.unwrap();
+            dm.append(&path, vec!["a".to_string()]).await.unwrap();
+
+            // crash after the snapshot is renamed, before the log is truncated
+            log.is_broken.store(true, Ordering::SeqCst);
+            dm.checkpoint().unwrap_err();
+            log.is_broken.store(false, Ordering::SeqCst);
+            assert_eq!(log.record_v.lock().unwrap().len(), 3);
+
+            let mut dm = open();
+            assert_eq!(dm.get(&path).await.unwrap(), ["b", "a"]);
+            assert_eq!(log.record_v.lock().unwrap().len(), 1);
+            dm.append(&path, vec!["c".to_string()]).await.unwrap();
+            let dm = open();
+            assert_eq!(dm.get(&path).await.unwrap(), ["b", "a", "c"]);
+            std::fs::remove_file(&file).unwrap();
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_not_commit_unlogged() {
+            let path = Path::from_str("root->list:item");
+            let log = MemLog::default();
+            let mut dm = MemDataManager::new(None).with_log(log.clone()).unwrap();
+            log.is_broken.store(true, Ordering::SeqCst);
+            let mut receiver = dm.subscribe().unwrap();
+            dm.set(&path, vec!["a".to_string()]).await.unwrap_err();
+            assert!(dm.get(&path).await.unwrap().is_empty());
+            assert!(receiver.try_recv().is_err());
+
+            // a failed checkpoint does not fail the commit
+            let file = std::env::temp_dir()
+                .join(crate::util::gen_value())
+                .join("missing.snapshot");
+            let mut dm = MemDataManager::open(&file, None).unwrap();
+            dm.set_checkpoint_interval(Some(std::time::Duration::ZERO));
+            dm.set(&path, vec!["a".to_string()]).await.unwrap();
+            assert_eq!(dm.get(&path).await.unwrap(), ["a"]);
+        }
+    }
+
+    #[cfg(test)]
+    mod test_snapshot {
+        use crate::{
+            err,
+            util::{
+                data::{AsDataManager, MemDataManager},
+                Path,
+            },
+        };
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn should_read_snapshot() {
+            let mut dm = MemDataManager::new(None);
+            let path = Path::from_str("root->list:item");
+            dm.set(&path, vec!["a".to_string()]).await.unwrap();
+            dm.begin().await.unwrap();
+            dm.append(&path, vec!["b".to_string()]).await.unwrap();
+
+            // committed only
+            let mut snapshot = dm.snapshot();
+            let reader = {
+                let snapshot = snapshot.clone();
+                let path = path.clone();
+                tokio::spawn(async move { snapshot.get(&path).await.unwrap() })
+            };
+            dm.commit().await.unwrap();
+            dm.append(&path, vec!["c".to_string()]).await.unwrap();
+
+            assert_eq!(reader.await.unwrap(), ["a"]);
+            assert_eq!(snapshot.get(&path).await.unwrap(), ["a"]);
+            assert_eq!(dm.get(&path).await.unwrap(), ["a", "b", "c"]);
+            assert_eq!(dm.snapshot().get(&path).await.unwrap(), ["a", "b", "c"]);
+
+            let e = snapshot.set(&path, vec![]).await.unwrap_err();
+            assert!(matches!(e.first().0, err::ErrorKind::PermissionDenied));
+        }
+    }
+}
+
+/// Table before the transaction and the changes to notify when committed.
+struct Transaction {
+    backup: mem_table::MemTable,
+    change_v: Vec<Change>,
+}
+
+/// File of [MemDataManager::open] and when it was written.
+struct SnapshotFile {
+    file: PathBuf,
+    /// Counts the checkpoints, the log starts from the one it has as its base.
+    generation: u64,
+    interval: Option<Duration>,
+    last: Instant,
+}
+
+fn map_io_err(e: io::Error, stack: &str) -> moon_err::Error<err::ErrorKind> {
+    log::error!("{e}\n{stack}");
+
+    moon_err::Error::new(
+        err::ErrorKind::Other(format!("IoError")),
+        e.to_string(),
+        stack.to_string(),
+    )
 }
 
 pub struct MemDataManager {
     auth: Auth,
     mem_table: mem_table::MemTable,
+    notifier: broadcast::Sender<Change>,
+    tx: Option<Transaction>,
+    snapshot_file: Option<SnapshotFile>,
+    log: Option<Box<dyn AsLogStorage>>,
+    /// Changes of the table not logged yet.
+    op_v: Vec<TableOp>,
 }
 
 impl MemDataManager {
@@ -75,28 +759,202 @@ impl MemDataManager {
         Self {
             auth,
             mem_table: mem_table::MemTable::new(),
+            notifier: broadcast::channel(64).0,
+            tx: None,
+            snapshot_file: None,
+            log: None,
+            op_v: Vec::new(),
         }
     }
-}
 
-impl AsDataManager for MemDataManager {
-    fn get_auth(&self) -> &Auth {
-        &self.auth
+    /// # Restore from the snapshot in `file` if any, [MemDataManager::checkpoint] writes it back.
+    pub fn open(file: impl Into<PathBuf>, auth: Auth) -> err::Result<Self> {
+        let file = file.into();
+        let (mem_table, generation) = match fs::File::open(&file) {
+            Ok(f) => mem_table::MemTable::load_from(io::BufReader::new(f))
+                .map_err(|e| map_io_err(e, "at open"))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (mem_table::MemTable::new(), 0),
+            Err(e) => return Err(map_io_err(e, "at open")),
+        };
+        let mut dm = Self::with_table(auth, mem_table);
+        dm.snapshot_file = Some(SnapshotFile {
+            file,
+            generation,
+            interval: None,
+            last: Instant::now(),
+        });
+        Ok(dm)
     }
 
-    fn append<'a, 'a1, 'f>(
-        &'a mut self,
-        path: &'a1 Path,
-        item_v: Vec<String>,
-    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
-    where
-        'a: 'f,
-        'a1: 'f,
-    {
-        if path.step_v.is_empty() {
-            return Box::pin(future::ready(Ok(())));
+    /// # Committed edges at this point, read while this manager writes on.
+    ///
+    /// Taking it is cheap, the table is shared until changed.
+    pub fn snapshot(&self) -> MemSnapshot {
+        let mem_table = match &self.tx {
+            Some(tx) => &tx.backup,
+            None => &self.mem_table,
+        };
+        MemSnapshot::new(Self::with_table(self.auth.clone(), mem_table.clone()))
+    }
+
+    fn with_table(auth: Auth, mem_table: mem_table::MemTable) -> Self {
+        let mut dm = Self::new(auth);
+        dm.mem_table = mem_table;
+        dm
+    }
+
+    /// # Keep every committed write in `log` before it returns.
+    ///
+    /// The writes `log` has after the snapshot are replayed first, [MemDataManager::checkpoint]
+    /// compacts it.
+    pub fn with_log(mut self, mut log: impl AsLogStorage + 'static) -> err::Result<Self> {
+        let generation = self.snapshot_file.as_ref().map_or(0, |s| s.generation);
+        let base = wal::base_record(generation);
+        let record_v = log.read_all().map_err(|e| map_io_err(e, "at with_log"))?;
+        if record_v.first() == Some(&base) {
+            for record in &record_v[1..] {
+                let op = TableOp::from_record(record).map_err(|e| map_io_err(e, "at with_log"))?;
+                self.mem_table
+                    .apply(&op)
+                    .map_err(|e| map_io_err(e, "at with_log"))?;
+            }
+        } else {
+            // empty, or written before the snapshot was
+            log.rewrite(&[base])
+                .map_err(|e| map_io_err(e, "at with_log"))?;
         }
-        Box::pin(async move {
+        self.log = Some(Box::new(log));
+        Ok(self)
+    }
+
+    /// Checkpoint after a write once `interval` passed since the last checkpoint, `None` means
+    /// only by [MemDataManager::checkpoint].
+    pub fn set_checkpoint_interval(&mut self, interval: Option<Duration>) {
+        if let Some(snapshot) = &mut self.snapshot_file {
+            snapshot.interval = interval;
+        }
+    }
+
+    /// # Write the committed edges to the file of [MemDataManager::open] and compact the log.
+    ///
+    /// The file is replaced at once, so a crash keeps the last snapshot. Without a file, the log
+    /// is compacted to the inserts of the edges.
+    pub fn checkpoint(&mut self) -> err::Result<()> {
+        if self.snapshot_file.is_none() && self.log.is_none() {
+            return Err(moon_err::Error::new(
+                err::ErrorKind::RuntimeError,
+                format!("neither a file nor a log"),
+                format!("at checkpoint"),
+            ));
+        }
+        let mem_table = match &self.tx {
+            Some(tx) => &tx.backup,
+            None => &self.mem_table,
+        };
+        let record_v = match &mut self.snapshot_file {
+            Some(snapshot) => {
+                let mut tmp = snapshot.file.clone().into_os_string();
+                tmp.push(".tmp");
+                let generation = snapshot.generation + 1;
+                let f = fs::File::create(&tmp).map_err(|e| map_io_err(e, "at checkpoint"))?;
+                let mut w = io::BufWriter::new(f);
+                mem_table
+                    .save_to(&mut w, generation)
+                    .and_then(|_| w.get_ref().sync_all())
+                    .and_then(|_| fs::rename(&tmp, &snapshot.file))
+                    .map_err(|e| map_io_err(e, "at checkpoint"))?;
+                snapshot.generation = generation;
+                snapshot.last = Instant::now();
+                // a log not rewritten after a crash here has an older base, so is dropped
+                vec![wal::base_record(generation)]
+            }
+            None => {
+                let mut record_v = vec![wal::base_record(0)];
+                record_v.extend(mem_table.to_op_v().iter().map(|op| op.to_record()));
+                record_v
+            }
+        };
+        if let Some(log) = &mut self.log {
+            log.rewrite(&record_v)
+                .map_err(|e| map_io_err(e, "at checkpoint"))?;
+        }
+        Ok(())
+    }
+
+    /// Change the table, logged by [MemDataManager::flush_log].
+    fn exec(&mut self, op: TableOp) -> err::Result<()> {
+        self.mem_table
+            .apply(&op)
+            .map_err(|e| map_io_err(e, "at exec"))?;
+        if self.log.is_some() {
+            self.op_v.push(op);
+        }
+        Ok(())
+    }
+
+    /// Append the changes to the log, kept until the transaction is committed.
+    fn flush_log(&mut self) -> err::Result<()> {
+        if self.tx.is_some() || self.op_v.is_empty() {
+            return Ok(());
+        }
+        if let Some(log) = &mut self.log {
+            let record_v: Vec<String> = self.op_v.iter().map(|op| op.to_record()).collect();
+            log.append(&record_v)
+                .map_err(|e| map_io_err(e, "at flush_log"))?;
+        }
+        self.op_v.clear();
+        Ok(())
+    }
+
+    fn checkpoint_if_due(&mut self) -> err::Result<()> {
+        if self.tx.is_some() {
+            return Ok(());
+        }
+        match &self.snapshot_file {
+            Some(SnapshotFile {
+                interval: Some(interval),
+                last,
+                ..
+            }) if last.elapsed() >= *interval => self.checkpoint(),
+            _ => Ok(()),
+        }
+    }
+
+    fn notify(&mut self, source: &str, paper: &str, code: &str) {
+        let change = Change {
+            source: source.to_string(),
+            paper: paper.to_string(),
+            code: code.to_string(),
+        };
+        match &mut self.tx {
+            Some(tx) => tx.change_v.push(change),
+            None => {
+                // no receiver is fine
+                let _ = self.notifier.send(change);
+            }
+        }
+    }
+
+    /// Notify every source once, for a write through `<-`.
+    fn notify_all(&mut self, source_v: &[String], step: &Step) {
+        let mut source_set = HashSet::new();
+        for source in source_v {
+            if source_set.insert(source) {
+                self.notify(source, &step.paper, &step.code);
+            }
+        }
+    }
+
+    /// Apply `op_v` in one pass over the table.
+    async fn write_op_v(&mut self, op_v: &[WriteOp]) -> err::Result<()> {
+        for op in op_v {
+            let path = op.path();
+            if path.step_v.is_empty() {
+                if let WriteOp::DeleteAll(_) = op {
+                    self.delete_node_v(&path.root_v)?;
+                }
+                continue;
+            }
             let mut path = path.clone();
             let step = path.step_v.pop().unwrap();
             if let Some(auth) = &self.auth {
@@ -104,19 +962,176 @@ impl AsDataManager for MemDataManager {
                     return Err(moon_err::Error::new(
                         err::ErrorKind::PermissionDenied,
                         format!("{}", step.paper),
-                        format!("at append"),
+                        format!("at write_batch"),
+                    ));
+                }
+            }
+            // a path of one step writes to its roots without a read
+            let root_v = if path.step_v.is_empty() {
+                path.root_v
+            } else {
+                self.get(&path).await?
+            };
+            self.write_step(op, &root_v, &step)?;
+        }
+        Ok(())
+    }
+
+    /// Apply `op` through its last `step` from `root_v`.
+    fn write_step(&mut self, op: &WriteOp, root_v: &[String], step: &Step) -> err::Result<()> {
+        let item_v: &[String] = match op {
+            WriteOp::Set(_, item_v) | WriteOp::Append(_, item_v) | WriteOp::Delete(_, item_v) => {
+                item_v
+            }
+            WriteOp::DeleteAll(_) => &[],
+        };
+        let is_set = matches!(op, WriteOp::Set(..) | WriteOp::DeleteAll(_));
+        if step.arrow == "<-" {
+            let mut source_v = Vec::new();
+            for target in root_v {
+                if is_set {
+                    source_v.extend(self.mem_table.get_source_v(&step.paper, &step.code, target));
+                    self.exec(TableOp::DeleteEdgeWithCodeTarget(
+                        step.paper.clone(),
+                        step.code.clone(),
+                        target.clone(),
+                    ))?;
+                }
+                for source in item_v {
+                    match op {
+                        WriteOp::Delete(..) => self.exec(TableOp::DeleteEdge(
+                            source.clone(),
+                            step.paper.clone(),
+                            step.code.clone(),
+                            target.clone(),
+                        ))?,
+                        _ => {
+                            self.exec(TableOp::InsertEdge(
+                                source.clone(),
+                                step.paper.clone(),
+                                step.code.clone(),
+                                target.clone(),
+                            ))?;
+                        }
+                    }
+                }
+            }
+            source_v.extend(item_v.iter().cloned());
+            self.notify_all(&source_v, step);
+            return Ok(());
+        }
+        if is_set {
+            for source in root_v {
+                self.exec(TableOp::DeleteEdgeWithSourceCode(
+                    source.clone(),
+                    step.paper.clone(),
+                    step.code.clone(),
+                ))?;
+            }
+        }
+        for source in root_v {
+            for target in item_v {
+                match op {
+                    WriteOp::Delete(..) => self.exec(TableOp::DeleteEdge(
+                        source.clone(),
+                        step.paper.clone(),
+                        step.code.clone(),
+                        target.clone(),
+                    ))?,
+                    _ => {
+                        self.exec(TableOp::InsertEdge(
+                            source.clone(),
+                            step.paper.clone(),
+                            step.code.clone(),
+                            target.clone(),
+                        ))?;
+                    }
+                }
+            }
+            self.notify(source, &step.paper, &step.code);
+        }
+        Ok(())
+    }
+
+    /// Remove every edge from or to `node_v`.
+    fn delete_node_v(&mut self, node_v: &[String]) -> err::Result<()> {
+        let mut change_set = HashSet::new();
+        for node in node_v {
+            let edge_v = self.mem_table.get_edge_v_of_node(node);
+            if let Some(auth) = &self.auth {
+                if let Some(edge) = edge_v.iter().find(|e| !auth.writer.contains(&e.paper)) {
+                    return Err(moon_err::Error::new(
+                        err::ErrorKind::PermissionDenied,
+                        format!("{}", edge.paper),
+                        format!("at delete_all"),
                     ));
                 }
             }
-            let root_v = self.get(&path).await?;
-            for source in &root_v {
-                for target in &item_v {
-                    self.mem_table
-                        .insert_edge(source, &step.paper, &step.code, target);
+            self.exec(TableOp::DeleteEdgeWithNode(node.clone()))?;
+            for edge in edge_v {
+                if change_set.insert((edge.source.clone(), edge.paper.clone(), edge.code.clone())) {
+                    self.notify(&edge.source, &edge.paper, &edge.code);
                 }
             }
-            Ok(())
-        })
+        }
+        Ok(())
+    }
+
+    /// Nodes at the other end of `step` from `node`, not repeated.
+    fn follow(&self, node: &str, step: &Step) -> Vec<String> {
+        match (step.arrow == "->", step.is_wildcard()) {
+            (true, true) => self.mem_table.get_target_v_of_paper(node, &step.paper),
+            (true, false) => self.mem_table.get_target_v(node, &step.paper, &step.code),
+            (false, true) => self.mem_table.get_source_v_of_paper(&step.paper, node),
+            (false, false) => self.mem_table.get_source_v(&step.paper, &step.code, node),
+        }
+    }
+
+    /// Nodes reached by following `step` from `repeat.min` to `repeat.max` times, each one once.
+    fn follow_repeat(&self, node: &str, step: &Step, repeat: Repeat) -> Vec<String> {
+        // without `max`, a depth over `min` is as good as `min`, so a cycle is walked only once
+        let cap = repeat.max.unwrap_or(repeat.min.max(1));
+        let mut visited = HashSet::new();
+        let mut rs_set = HashSet::new();
+        let mut rs = Vec::new();
+        let mut queue = VecDeque::new();
+        visited.insert((node.to_string(), 0));
+        queue.push_back((node.to_string(), 0));
+        while let Some((node, depth)) = queue.pop_front() {
+            if depth >= repeat.min && rs_set.insert(node.clone()) {
+                rs.push(node.clone());
+            }
+            if let Some(max) = repeat.max {
+                if depth >= max {
+                    continue;
+                }
+            }
+            let n_depth = min(depth + 1, cap);
+            for next in self.follow(&node, step) {
+                if visited.insert((next.clone(), n_depth)) {
+                    queue.push_back((next, n_depth));
+                }
+            }
+        }
+        rs
+    }
+}
+
+impl AsDataManager for MemDataManager {
+    fn get_auth(&self) -> &Auth {
+        &self.auth
+    }
+
+    fn append<'a, 'a1, 'f>(
+        &'a mut self,
+        path: &'a1 Path,
+        item_v: Vec<String>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        self.write_batch(vec![WriteOp::Append(path.clone(), item_v)])
     }
 
     fn set<'a, 'a1, 'f>(
@@ -128,33 +1143,62 @@ impl AsDataManager for MemDataManager {
         'a: 'f,
         'a1: 'f,
     {
-        if path.step_v.is_empty() {
-            return Box::pin(future::ready(Ok(())));
-        }
+        self.write_batch(vec![WriteOp::Set(path.clone(), item_v)])
+    }
+
+    fn delete<'a, 'a1, 'f>(
+        &'a mut self,
+        path: &'a1 Path,
+        item_v: Vec<String>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        self.write_batch(vec![WriteOp::Delete(path.clone(), item_v)])
+    }
+
+    fn delete_all<'a, 'a1, 'f>(
+        &'a mut self,
+        path: &'a1 Path,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        self.write_batch(vec![WriteOp::DeleteAll(path.clone())])
+    }
+
+    fn write_batch<'a, 'f>(
+        &'a mut self,
+        op_v: Vec<WriteOp>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
         Box::pin(async move {
-            let mut path = path.clone();
-            let step = path.step_v.pop().unwrap();
-            if let Some(auth) = &self.auth {
-                if !auth.writer.contains(&step.paper) {
-                    return Err(moon_err::Error::new(
-                        err::ErrorKind::PermissionDenied,
-                        format!("{}", step.paper),
-                        format!("at set"),
-                    ));
-                }
-            }
-            let root_v = self.get(&path).await?;
-            for source in &root_v {
-                self.mem_table
-                    .delete_edge_with_source_code(source, &step.paper, &step.code);
+            let is_own = self.tx.is_none();
+            if is_own {
+                self.begin().await?;
             }
-            for source in &root_v {
-                for target in &item_v {
-                    self.mem_table
-                        .insert_edge(source, &step.paper, &step.code, target);
+            let backup = self.mem_table.clone();
+            let op_cnt = self.op_v.len();
+            let change_cnt = self.tx.as_ref().unwrap().change_v.len();
+            match self.write_op_v(&op_v).await {
+                Ok(()) if is_own => self.commit().await,
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    // undo the batch only, not the transaction it is in
+                    self.mem_table = backup;
+                    self.op_v.truncate(op_cnt);
+                    if is_own {
+                        self.tx = None;
+                    } else {
+                        self.tx.as_mut().unwrap().change_v.truncate(change_cnt);
+                    }
+                    Err(e)
                 }
             }
-            Ok(())
         })
     }
 
@@ -183,19 +1227,33 @@ impl AsDataManager for MemDataManager {
                         ));
                     }
                 }
-                if step.arrow == "->" {
-                    let mut n_rs = Vec::new();
-                    for source in &rs {
-                        n_rs.extend(self.mem_table.get_target_v(source, &step.paper, &step.code));
+                let mut n_rs = Vec::new();
+                for node in &rs {
+                    let item_v = match step.repeat {
+                        Some(repeat) => self.follow_repeat(node, &step, repeat),
+                        None => self.follow(node, &step),
+                    };
+                    match step.index {
+                        Some(index) => n_rs.extend(index.select(&item_v)),
+                        None => n_rs.extend(item_v),
                     }
-                    rs = n_rs;
-                } else {
-                    let mut n_rs = Vec::new();
-                    for target in &rs {
-                        n_rs.extend(self.mem_table.get_source_v(&step.paper, &step.code, target));
+                }
+                for filter in &step.filter_v {
+                    let mut f_rs = Vec::with_capacity(n_rs.len());
+                    for node in n_rs {
+                        let item_v = self
+                            .get(&Path {
+                                root_v: vec![node.clone()],
+                                step_v: filter.step_v.clone(),
+                            })
+                            .await?;
+                        if filter.is_match(&item_v) {
+                            f_rs.push(node);
+                        }
                     }
-                    rs = n_rs;
+                    n_rs = f_rs;
                 }
+                rs = n_rs;
             }
             Ok(rs)
         })
@@ -216,4 +1274,73 @@ impl AsDataManager for MemDataManager {
             Ok(rs)
         })
     }
+
+    fn subscribe(&self) -> Option<broadcast::Receiver<Change>> {
+        Some(self.notifier.subscribe())
+    }
+
+    fn begin<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        if self.tx.is_some() {
+            return Box::pin(future::ready(Err(moon_err::Error::new(
+                err::ErrorKind::RuntimeError,
+                format!("transaction already begun"),
+                format!("at begin"),
+            ))));
+        }
+        self.tx = Some(Transaction {
+            backup: self.mem_table.clone(),
+            change_v: Vec::new(),
+        });
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn commit<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        match self.tx.take() {
+            Some(tx) => {
+                if let Err(e) = self.flush_log() {
+                    // not kept by the log, so not committed
+                    self.mem_table = tx.backup;
+                    self.op_v.clear();
+                    return Box::pin(future::ready(Err(e)));
+                }
+                for change in tx.change_v {
+                    let _ = self.notifier.send(change);
+                }
+                // committed anyway, tried again after the next write
+                if let Err(e) = self.checkpoint_if_due() {
+                    log::error!("{:?}\nat commit", e);
+                }
+                Box::pin(future::ready(Ok(())))
+            }
+            None => Box::pin(future::ready(Err(moon_err::Error::new(
+                err::ErrorKind::RuntimeError,
+                format!("no transaction"),
+                format!("at commit"),
+            )))),
+        }
+    }
+
+    fn rollback<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        match self.tx.take() {
+            Some(tx) => {
+                self.mem_table = tx.backup;
+                self.op_v.clear();
+                Box::pin(future::ready(Ok(())))
+            }
+            None => Box::pin(future::ready(Err(moon_err::Error::new(
+                err::ErrorKind::RuntimeError,
+                format!("no transaction"),
+                format!("at rollback"),
+            )))),
+        }
+    }
 }
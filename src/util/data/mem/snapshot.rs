@@ -0,0 +1,118 @@
+use std::{future, pin::Pin};
+
+use crate::{
+    err,
+    util::{
+        data::{AsDataManager, Auth, Fu},
+        Path,
+    },
+};
+
+use super::MemDataManager;
+
+fn read_only(stack: &str) -> moon_err::Error<err::ErrorKind> {
+    moon_err::Error::new(
+        err::ErrorKind::PermissionDenied,
+        format!("snapshot is read only"),
+        stack.to_string(),
+    )
+}
+
+/// # Read-only view of a [MemDataManager] taken by [MemDataManager::snapshot].
+///
+/// Not changed by later writes, every write fails.
+pub struct MemSnapshot {
+    dm: MemDataManager,
+}
+
+impl MemSnapshot {
+    pub(super) fn new(dm: MemDataManager) -> Self {
+        Self { dm }
+    }
+}
+
+impl Clone for MemSnapshot {
+    fn clone(&self) -> Self {
+        Self::new(MemDataManager::with_table(
+            self.dm.auth.clone(),
+            self.dm.mem_table.clone(),
+        ))
+    }
+}
+
+impl AsDataManager for MemSnapshot {
+    fn get_auth(&self) -> &Auth {
+        self.dm.get_auth()
+    }
+
+    fn append<'a, 'a1, 'f>(
+        &'a mut self,
+        _: &'a1 Path,
+        _: Vec<String>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(future::ready(Err(read_only("at append"))))
+    }
+
+    fn set<'a, 'a1, 'f>(
+        &'a mut self,
+        _: &'a1 Path,
+        _: Vec<String>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(future::ready(Err(read_only("at set"))))
+    }
+
+    fn delete<'a, 'a1, 'f>(
+        &'a mut self,
+        _: &'a1 Path,
+        _: Vec<String>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(future::ready(Err(read_only("at delete"))))
+    }
+
+    fn delete_all<'a, 'a1, 'f>(
+        &'a mut self,
+        _: &'a1 Path,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(future::ready(Err(read_only("at delete_all"))))
+    }
+
+    fn get<'a, 'a1, 'f>(
+        &'a self,
+        path: &'a1 Path,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<Vec<String>>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        self.dm.get(path)
+    }
+
+    fn get_code_v<'a, 'a1, 'a2, 'f>(
+        &'a self,
+        root: &'a1 str,
+        space: &'a2 str,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<Vec<String>>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+        'a2: 'f,
+    {
+        self.dm.get_code_v(root, space)
+    }
+}
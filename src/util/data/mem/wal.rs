@@ -0,0 +1,76 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+/// First record of a log, the checkpoint generation of the snapshot it starts from.
+pub(super) fn base_record(generation: u64) -> String {
+    format!("base\t{generation}")
+}
+
+/// # Where the log of a [super::MemDataManager] is kept.
+///
+/// A record is one line without `\n`. Implement it over any storage where files are not
+/// available.
+pub trait AsLogStorage: Send + Sync {
+    /// Every record in the order appended, without a record torn by a crash.
+    fn read_all(&mut self) -> io::Result<Vec<String>>;
+
+    /// Append `record_v`, kept once returned.
+    fn append(&mut self, record_v: &[String]) -> io::Result<()>;
+
+    /// Replace every record by `record_v` at once.
+    fn rewrite(&mut self, record_v: &[String]) -> io::Result<()>;
+}
+
+/// Log in one file, a record a line.
+pub struct FileLog {
+    file: PathBuf,
+}
+
+impl FileLog {
+    pub fn new(file: impl Into<PathBuf>) -> Self {
+        Self { file: file.into() }
+    }
+}
+
+impl AsLogStorage for FileLog {
+    fn read_all(&mut self) -> io::Result<Vec<String>> {
+        let mut content = String::new();
+        match fs::File::open(&self.file) {
+            Ok(mut f) => f.read_to_string(&mut content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        // the last line is torn if not ended
+        let mut line_v: Vec<&str> = content.split('\n').collect();
+        line_v.pop();
+        Ok(line_v.into_iter().map(|line| line.to_string()).collect())
+    }
+
+    fn append(&mut self, record_v: &[String]) -> io::Result<()> {
+        let mut f = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)?;
+        let mut content = String::new();
+        for record in record_v {
+            content.push_str(record);
+            content.push('\n');
+        }
+        f.write_all(content.as_bytes())?;
+        f.sync_data()
+    }
+
+    fn rewrite(&mut self, record_v: &[String]) -> io::Result<()> {
+        let mut tmp = self.file.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut f = fs::File::create(&tmp)?;
+        for record in record_v {
+            writeln!(f, "{record}")?;
+        }
+        f.sync_all()?;
+        fs::rename(&tmp, &self.file)
+    }
+}
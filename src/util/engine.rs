@@ -1,71 +1,245 @@
-use std::{future, pin::Pin};
+use std::{
+    collections::HashMap,
+    future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::broadcast;
 
 use crate::{err, util::Path};
 
 use super::{
-    data::{AsDataManager, Fu, MemDataManager},
+    data::{self, AsDataManager, Change, Fu, MemDataManager, WriteOp},
     func, PathPart,
 };
 
+pub mod cache;
+pub mod limit;
+pub mod parser;
+pub mod registry;
+pub mod trace;
+
 mod dep {
+    use std::{collections::HashMap, time::Duration};
+
     use crate::{
         err,
-        util::{self, engine::Inc, Path},
+        util::{
+            self,
+            data::Change,
+            engine::{Code, Inc},
+            Path,
+        },
     };
 
-    pub fn parse_script1(script: &[String]) -> err::Result<Vec<Inc>> {
-        let mut inc_v = Vec::new();
-        for line in script {
-            if line.is_empty() {
-                continue;
-            }
+    use super::parser;
 
-            let word_v: Vec<&str> = line.split(' ').collect();
-            if word_v.len() < 4 {
-                return Err(moon_err::Error::new(
-                    err::ErrorKind::SyntaxError,
-                    format!("{line}: less than 4 words in a line"),
-                    format!("at parse_script1"),
-                ));
+    #[derive(Default)]
+    struct Lower<'p> {
+        expr_cnt: usize,
+        param_mp: Option<&'p HashMap<String, Vec<String>>>,
+        /// Placeholders without a value.
+        unbound_v: Vec<String>,
+    }
+
+    impl<'p> Lower<'p> {
+        /// Path of the argument, an expression is pushed to `code_v` first with a generated
+        /// output.
+        fn arg_2_path(&mut self, arg: &parser::Arg, code_v: &mut Vec<Code>) -> Path {
+            match arg {
+                parser::Arg::Path(lit) => {
+                    let mut path = Path::from_str(&lit.text);
+                    if let Some(param_mp) = self.param_mp {
+                        // only an unquoted root is a placeholder
+                        if let Some(name) = placeholder(&lit.text, &path) {
+                            match param_mp.get(name) {
+                                Some(value_v) => path.root_v = value_v.clone(),
+                                None => self.unbound_v.push(name.to_string()),
+                            }
+                        }
+                    }
+                    path
+                }
+                parser::Arg::Str(lit) => Path {
+                    root_v: vec![lit.value.clone()],
+                    step_v: vec![],
+                },
+                parser::Arg::Expr(expr) => {
+                    let function = self.arg_2_path(&expr.function, code_v);
+                    let input = self.arg_2_path(&expr.input, code_v);
+                    let input1 = self.arg_2_path(&expr.input1, code_v);
+                    let output = Path::from_str(&format!("$->$:__expr{}", self.expr_cnt));
+                    self.expr_cnt += 1;
+                    code_v.push(Code::Inc(Inc {
+                        output: output.clone(),
+                        ret_v: vec![],
+                        function,
+                        input,
+                        input1,
+                    }));
+                    output
+                }
             }
-            if word_v.len() == 5 {
-                if word_v[1] == "=" {
-                    inc_v.push(Inc {
-                        output: Path::from_str(word_v[0].trim()),
-                        function: Path::from_str(word_v[2].trim()),
-                        input: Path::from_str(word_v[3].trim()),
-                        input1: Path::from_str(word_v[4].trim()),
-                    });
-                } else if word_v[1] == "+=" {
-                    inc_v.push(Inc {
+        }
+
+        fn lower_stmt(&mut self, stmt: &parser::Stmt, code_v: &mut Vec<Code>) {
+            let function = self.arg_2_path(&stmt.function, code_v);
+            let input = self.arg_2_path(&stmt.input, code_v);
+            let input1 = self.arg_2_path(&stmt.input1, code_v);
+            let output = self.arg_2_path(&stmt.output, code_v);
+            let ret_v = stmt
+                .ret_v
+                .iter()
+                .map(|ret| self.arg_2_path(ret, code_v))
+                .collect();
+            match stmt.op {
+                parser::Operator::Call | parser::Operator::Assign => code_v.push(Code::Inc(Inc {
+                    output,
+                    ret_v,
+                    function,
+                    input,
+                    input1,
+                })),
+                parser::Operator::AddAssign => {
+                    code_v.push(Code::Inc(Inc {
                         output: Path::from_str("$->$:temp"),
-                        function: Path::from_str(word_v[2].trim()),
-                        input: Path::from_str(word_v[3].trim()),
-                        input1: Path::from_str(word_v[4].trim()),
-                    });
-                    inc_v.push(Inc {
-                        output: Path::from_str(word_v[0].trim()),
+                        ret_v: vec![],
+                        function,
+                        input,
+                        input1,
+                    }));
+                    code_v.push(Code::Inc(Inc {
+                        output: output.clone(),
+                        ret_v: vec![],
                         function: Path::from_str("+="),
-                        input: Path::from_str(word_v[0].trim()),
+                        input: output,
                         input1: Path::from_str("$->$:temp"),
-                    });
-                } else {
-                    return Err(moon_err::Error::new(
-                        err::ErrorKind::SyntaxError,
-                        format!("when parse_script:\n\tunknown operator"),
-                        format!("at parse_script1"),
-                    ));
+                    }));
+                }
+            }
+        }
+
+        fn lower_node_v(&mut self, node_v: &[parser::Node]) -> Vec<Code> {
+            let mut code_v = Vec::new();
+            for node in node_v {
+                match node {
+                    parser::Node::Stmt(stmt) => self.lower_stmt(stmt, &mut code_v),
+                    parser::Node::If {
+                        cond,
+                        then_v,
+                        else_v,
+                        ..
+                    } => {
+                        let cond = self.arg_2_path(cond, &mut code_v);
+                        code_v.push(Code::If {
+                            cond,
+                            then_v: self.lower_node_v(then_v),
+                            else_v: self.lower_node_v(else_v),
+                        });
+                    }
+                    parser::Node::While { cond, body_v, .. } => {
+                        let mut cond_v = Vec::new();
+                        let cond = self.arg_2_path(cond, &mut cond_v);
+                        code_v.push(Code::While {
+                            cond_v,
+                            cond,
+                            body_v: self.lower_node_v(body_v),
+                        });
+                    }
+                    parser::Node::For {
+                        item, path, body_v, ..
+                    } => {
+                        let item = self.arg_2_path(item, &mut code_v);
+                        let path = self.arg_2_path(path, &mut code_v);
+                        code_v.push(Code::For {
+                            item,
+                            path,
+                            body_v: self.lower_node_v(body_v),
+                        });
+                    }
                 }
-                continue;
             }
-            inc_v.push(Inc {
-                output: Path::from_str(word_v[0].trim()),
-                function: Path::from_str(word_v[1].trim()),
-                input: Path::from_str(word_v[2].trim()),
-                input1: Path::from_str(word_v[3].trim()),
-            });
+            code_v
+        }
+    }
+
+    /// Name of the placeholder root `:name` of `path`, parsed from `text`.
+    fn placeholder<'t>(text: &'t str, path: &Path) -> Option<&'t str> {
+        if path.root_v.len() != 1 || !text.starts_with(':') {
+            return None;
+        }
+        let name = &path.root_v[0][1..];
+        if name.is_empty() || !name.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+            return None;
+        }
+        Some(&text[1..1 + name.len()])
+    }
+
+    pub fn parse_script1(script: &[String]) -> err::Result<Vec<Code>> {
+        parse_script_with(script, None)
+    }
+
+    /// Roots `:name` are replaced by the values in `param_mp`, if given.
+    pub fn parse_script_with(
+        script: &[String],
+        param_mp: Option<&HashMap<String, Vec<String>>>,
+    ) -> err::Result<Vec<Code>> {
+        let script = parser::parse(script).map_err(|diagnostic_v| {
+            moon_err::Error::new(
+                err::ErrorKind::SyntaxError,
+                diagnostic_v
+                    .iter()
+                    .map(|diagnostic| diagnostic.to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                format!("at parse_script1"),
+            )
+        })?;
+
+        let mut lower = Lower {
+            param_mp,
+            ..Default::default()
+        };
+        let code_v = lower.lower_node_v(&script.node_v);
+        if !lower.unbound_v.is_empty() {
+            return Err(moon_err::Error::new(
+                err::ErrorKind::NotFound,
+                format!("unbound parameter: {}", lower.unbound_v.join(", ")),
+                format!("at parse_script_with"),
+            ));
+        }
+        Ok(code_v)
+    }
+
+    /// Whether `change` may change the targets of `path`, which has only one step.
+    pub fn is_watched(path: &Path, change: &Change) -> bool {
+        let step = &path.step_v[0];
+        // a filter reads other edges, and the index of a node moves with its siblings
+        if !step.filter_v.is_empty() || step.index.is_some() {
+            return true;
+        }
+        step.paper == change.paper
+            && (step.is_wildcard() || step.code == change.code)
+            && (step.arrow != "->" || step.repeat.is_some() || path.root_v.contains(&change.source))
+    }
+
+    /// Milliseconds in the first item, no timeout if empty.
+    pub fn parse_timeout(item_v: &[String]) -> err::Result<Option<Duration>> {
+        match item_v.first() {
+            Some(item) => item
+                .parse::<u64>()
+                .map(|ms| Some(Duration::from_millis(ms)))
+                .map_err(|e| {
+                    moon_err::Error::new(
+                        err::ErrorKind::RuntimeError,
+                        format!("invalid timeout {item}: {e}"),
+                        format!("at parse_timeout"),
+                    )
+                }),
+            None => Ok(None),
         }
-        Ok(inc_v)
     }
 
     #[inline]
@@ -94,6 +268,27 @@ pub trait AsEdgeEngine {
     where
         'a: 'f,
         'a1: 'f;
+
+    /// # Execute a script whose roots `:name` are bound to the values of `name` in `param_mp`.
+    ///
+    /// A value is bound to the parsed [Path], so it is never escaped or parsed, and a quoted
+    /// `':name'` is a plain root. An unbound placeholder is an error.
+    fn execute_script_with<'a, 'a1, 'a2, 'f>(
+        &'a mut self,
+        _: &'a1 [String],
+        _: &'a2 HashMap<String, Vec<String>>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<Vec<String>>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+        'a2: 'f,
+    {
+        Box::pin(future::ready(Err(moon_err::Error::new(
+            err::ErrorKind::NotFound,
+            format!("parameters not supported"),
+            format!("at execute_script_with"),
+        ))))
+    }
 }
 
 /// Let impl [AsDataManager] be using for [AsEdgeEngine].
@@ -103,6 +298,11 @@ where
 {
     global: &'g mut DM,
     temp: MemDataManager,
+    registry: Arc<registry::FunctionRegistry>,
+    ctx: limit::Context,
+    is_transactional: bool,
+    observer: Option<Arc<dyn trace::AsObserver>>,
+    cache: Arc<cache::ScriptCache>,
 }
 
 impl<'g, DM> AsEdgeEngine for EdgeEngine<'g, DM>
@@ -118,54 +318,46 @@ where
         'a1: 'f,
     {
         Box::pin(async move {
-            let mut inc_v = dep::parse_script1(&script)?;
-            if inc_v.is_empty() {
-                return Ok(vec![]);
+            if !self.ctx.is_top() {
+                let code_v = self.cache.parse(script)?;
+                if code_v.is_empty() {
+                    return Ok(vec![]);
+                }
+                return self.execute_code_v(&code_v).await;
             }
 
-            for inc in &mut inc_v {
-                dep::unwrap_inc(inc);
-                let func_name_v = self.get(&inc.function).await?;
-                if func_name_v.is_empty() {
-                    return Err(moon_err::Error::new(
-                        err::ErrorKind::RuntimeError,
-                        format!("no funtion: {}\nat invoke_inc", inc.function.to_string()),
-                        format!("at execute_script"),
-                    ));
-                }
+            let code_v = dep::parse_script1(&script)?;
+            self.execute_top(&code_v).await
+        })
+    }
 
-                if let Err(e) = self
-                    .call(&inc.output, &func_name_v[0], &inc.input, &inc.input1)
-                    .await
-                {
-                    if let err::ErrorKind::NotFound = e.first().0 {
-                        let input_item_v = self.get(&inc.input).await?;
-                        let input1_item_v = self.get(&inc.input1).await?;
-
-                        let rs = {
-                            let mut sub_engine = EdgeEngine::new(self.get_global_mut());
-
-                            let _ = sub_engine
-                                .set(&Path::from_str("$->$:input"), input_item_v)
-                                .await;
-                            let _ = sub_engine
-                                .set(&Path::from_str("$->$:input1"), input1_item_v)
-                                .await;
-                            sub_engine.execute_script(&func_name_v).await?
-                        };
-
-                        self.set(&inc.output, rs).await?;
-                    } else {
-                        return Err(e);
-                    }
+    fn execute_script_with<'a, 'a1, 'a2, 'f>(
+        &'a mut self,
+        script: &'a1 [String],
+        param_mp: &'a2 HashMap<String, Vec<String>>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<Vec<String>>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+        'a2: 'f,
+    {
+        Box::pin(async move {
+            let code_v = dep::parse_script_with(script, Some(param_mp))?;
+            if !self.ctx.is_top() {
+                if code_v.is_empty() {
+                    return Ok(vec![]);
                 }
+                return self.execute_code_v(&code_v).await;
             }
-
-            self.get(&Path::from_str("$->$:output")).await
+            self.execute_top(&code_v).await
         })
     }
 }
 
+/// Registers of the engine a script can not return a named value by, nor by a name starting with
+/// `__`.
+const RESERVED_RET_NAME_V: [&str; 5] = ["output", "input", "input1", "ret", "temp"];
+
 impl<'g, DM> EdgeEngine<'g, DM>
 where
     DM: AsDataManager,
@@ -174,6 +366,11 @@ where
         Self {
             global,
             temp: MemDataManager::new(None),
+            registry: Arc::new(registry::FunctionRegistry::new()),
+            ctx: limit::Context::default(),
+            is_transactional: false,
+            observer: None,
+            cache: Arc::new(cache::ScriptCache::default()),
         }
     }
 
@@ -202,37 +399,402 @@ where
     }
 
     pub fn new_with_temp(global: &'g mut DM, temp: MemDataManager) -> Self {
-        Self { global, temp }
+        Self {
+            global,
+            temp,
+            registry: Arc::new(registry::FunctionRegistry::new()),
+            ctx: limit::Context::default(),
+            is_transactional: false,
+            observer: None,
+            cache: Arc::new(cache::ScriptCache::default()),
+        }
     }
 
-    pub fn while1<'a, 'a1, 'f>(
-        &'a self,
-        path: &'a1 Path,
+    /// Share `registry` with other engines.
+    pub fn set_registry(&mut self, registry: Arc<registry::FunctionRegistry>) {
+        self.registry = registry;
+    }
+
+    #[inline]
+    pub fn get_registry(&self) -> &registry::FunctionRegistry {
+        &self.registry
+    }
+
+    /// The registry is copied first if it is shared.
+    #[inline]
+    pub fn get_registry_mut(&mut self) -> &mut registry::FunctionRegistry {
+        Arc::make_mut(&mut self.registry)
+    }
+
+    /// All functions can be called by scripts of this engine.
+    pub fn function_info_v(&self) -> Vec<registry::FunctionInfo> {
+        self.registry.info_v()
+    }
+
+    /// # Execute every script in a transaction of the global.
+    ///
+    /// Writes of the script, including the scripts it calls, are committed when it succeeds or
+    /// rolled back when it fails.
+    pub fn set_transactional(&mut self, is_transactional: bool) {
+        self.is_transactional = is_transactional;
+    }
+
+    /// Run a script of the top engine, in a transaction if transactional.
+    fn execute_top<'a, 'a1, 'f>(
+        &'a mut self,
+        code_v: &'a1 [Code],
+    ) -> Pin<Box<dyn Fu<Output = err::Result<Vec<String>>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(async move {
+            if code_v.is_empty() {
+                return Ok(vec![]);
+            }
+
+            self.ctx.start();
+            if !self.is_transactional {
+                return self.execute_code_v(code_v).await;
+            }
+
+            self.global.begin().await?;
+            match self.execute_code_v(code_v).await {
+                Ok(rs) => {
+                    self.global.commit().await?;
+                    Ok(rs)
+                }
+                Err(e) => {
+                    if let Err(rollback_e) = self.global.rollback().await {
+                        log::error!("{:?}\nat execute_script", rollback_e);
+                    }
+                    Err(e)
+                }
+            }
+        })
+    }
+
+    fn execute_code_v<'a, 'a1, 'f>(
+        &'a mut self,
+        code_v: &'a1 [Code],
+    ) -> Pin<Box<dyn Fu<Output = err::Result<Vec<String>>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(async move {
+            let mut index = 0;
+            self.execute_block(code_v, &mut index).await?;
+            self.get(&Path::from_str("$->$:output")).await
+        })
+    }
+
+    /// `index` counts the instructions executed in the script.
+    fn execute_block<'a, 'a1, 'a2, 'f>(
+        &'a mut self,
+        code_v: &'a1 [Code],
+        index: &'a2 mut usize,
     ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
     where
         'a: 'f,
         'a1: 'f,
+        'a2: 'f,
     {
         Box::pin(async move {
-            let mut path = path.clone();
-            let step = path.step_v.pop().unwrap();
-            let root_v = self.get(&path).await?;
-            let path = Path {
-                root_v,
-                step_v: vec![step],
-            };
-            loop {
-                if path.is_temp() {
-                    if !self.temp.get(&path).await?.is_empty() {
-                        return Ok(());
+            for code in code_v {
+                match code {
+                    Code::Inc(inc) => {
+                        self.execute_inc(inc, *index).await?;
+                        *index += 1;
                     }
+                    Code::If {
+                        cond,
+                        then_v,
+                        else_v,
+                    } => {
+                        if self.get(cond).await?.is_empty() {
+                            self.execute_block(else_v, index).await?;
+                        } else {
+                            self.execute_block(then_v, index).await?;
+                        }
+                    }
+                    Code::While {
+                        cond_v,
+                        cond,
+                        body_v,
+                    } => loop {
+                        // every test is counted, so an empty loop is limited too
+                        self.ctx.step()?;
+                        self.execute_block(cond_v, index).await?;
+                        if self.get(cond).await?.is_empty() {
+                            break;
+                        }
+                        self.execute_block(body_v, index).await?;
+                    },
+                    Code::For { item, path, body_v } => {
+                        for item_value in self.get(path).await? {
+                            self.ctx.step()?;
+                            self.set(item, vec![item_value]).await?;
+                            self.execute_block(body_v, index).await?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn execute_inc<'a, 'a1, 'f>(
+        &'a mut self,
+        inc: &'a1 Inc,
+        index: usize,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(async move {
+            self.ctx.step()?;
+            // a generated value is new every time the instruction is executed
+            let mut inc = inc.clone();
+            dep::unwrap_inc(&mut inc);
+            let func_name_v = self.get(&inc.function).await?;
+            if func_name_v.is_empty() {
+                return Err(moon_err::Error::new(
+                    err::ErrorKind::RuntimeError,
+                    format!("no funtion: {}\nat invoke_inc", inc.function.to_string()),
+                    format!("at execute_script"),
+                ));
+            }
+
+            let observer = match &self.observer {
+                Some(observer) => observer.clone(),
+                None => return self.invoke_inc(&inc, &func_name_v).await,
+            };
+            let event = trace::IncEvent {
+                depth: self.ctx.depth(),
+                index,
+                inc: inc.clone(),
+                function: func_name_v[0].clone(),
+                input: self.temp_2_global(&inc.input).await?,
+                input1: self.temp_2_global(&inc.input1).await?,
+                input_item_v: self.get(&inc.input).await?,
+                input1_item_v: self.get(&inc.input1).await?,
+            };
+            observer.before(&event).await?;
+            let start = Instant::now();
+            let rs = self.invoke_inc(&inc, &func_name_v).await;
+            let duration = start.elapsed();
+            let (output_item_v, error) = match &rs {
+                Ok(()) => (self.get(&inc.output).await?, None),
+                Err(e) => (vec![], Some(e.to_string())),
+            };
+            observer
+                .after(
+                    &event,
+                    &trace::IncResult {
+                        output_item_v,
+                        duration,
+                        error,
+                    },
+                )
+                .await?;
+            rs
+        })
+    }
+
+    /// # Call the function, or execute it as a script if it is not found.
+    ///
+    /// The script returns `$->$:output` and the named values listed in `$->$:ret`, each one in
+    /// `$->$:name`, bound to `inc.output` and `inc.ret_v` in order. A returned value missing is
+    /// bound as empty, a name of a register of the engine is a `RuntimeError`.
+    fn invoke_inc<'a, 'a1, 'a2, 'f>(
+        &'a mut self,
+        inc: &'a1 Inc,
+        func_name_v: &'a2 [String],
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+        'a2: 'f,
+    {
+        Box::pin(async move {
+            let mut ret_item_v_v = Vec::new();
+            if let Err(e) = self
+                .call(&inc.output, &func_name_v[0], &inc.input, &inc.input1)
+                .await
+            {
+                if let err::ErrorKind::NotFound = e.first().0 {
+                    let input_item_v = self.get(&inc.input).await?;
+                    let input1_item_v = self.get(&inc.input1).await?;
+
+                    let rs = {
+                        let mut sub_engine = self.new_sub_engine()?;
+
+                        let _ = sub_engine
+                            .set(&Path::from_str("$->$:input"), input_item_v)
+                            .await;
+                        let _ = sub_engine
+                            .set(&Path::from_str("$->$:input1"), input1_item_v)
+                            .await;
+                        let rs = sub_engine.execute_script(func_name_v).await?;
+
+                        if !inc.ret_v.is_empty() {
+                            let name_v = sub_engine.get(&Path::from_str("$->$:ret")).await?;
+                            for name in name_v.iter().take(inc.ret_v.len()) {
+                                if RESERVED_RET_NAME_V.contains(&name.as_str())
+                                    || name.starts_with("__")
+                                {
+                                    return Err(moon_err::Error::new(
+                                        err::ErrorKind::RuntimeError,
+                                        format!("reserved name: {name}"),
+                                        format!("at invoke_inc"),
+                                    ));
+                                }
+                                let mut path = Path::from_str("$->$:ret");
+                                path.step_v[0].code = name.clone();
+                                ret_item_v_v.push(sub_engine.get(&path).await?);
+                            }
+                        }
+                        rs
+                    };
+
+                    self.set(&inc.output, rs).await?;
                 } else {
-                    if !self.global.get(&path).await?.is_empty() {
-                        return Ok(());
+                    return Err(e);
+                }
+            }
+
+            ret_item_v_v.resize(inc.ret_v.len(), vec![]);
+            for (ret, item_v) in inc.ret_v.iter().zip(ret_item_v_v) {
+                self.set(ret, item_v).await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// # Observe every instruction executed by this engine and the scripts it calls.
+    ///
+    /// See [trace::Recorder] for a readable trace.
+    pub fn set_observer(&mut self, observer: Arc<dyn trace::AsObserver>) {
+        self.observer = Some(observer);
+    }
+
+    pub fn clear_observer(&mut self) {
+        self.observer = None;
+    }
+
+    /// Share the cache of the parsed functions called by scripts with other engines.
+    pub fn set_script_cache(&mut self, cache: Arc<cache::ScriptCache>) {
+        self.cache = cache;
+    }
+
+    #[inline]
+    pub fn get_script_cache(&self) -> &cache::ScriptCache {
+        &self.cache
+    }
+
+    /// Limit the scripts executed by this engine, `None` means unlimited.
+    pub fn set_limit(&mut self, limit: limit::Limit) {
+        self.ctx.limit = limit;
+    }
+
+    #[inline]
+    pub fn get_limit(&self) -> &limit::Limit {
+        &self.ctx.limit
+    }
+
+    /// Share `cancel_token` with other engines.
+    pub fn set_cancel_token(&mut self, cancel_token: limit::CancelToken) {
+        self.ctx.cancel_token = cancel_token;
+    }
+
+    /// Cancel the running script from elsewhere by the returned token, it stays cancelled for the
+    /// later scripts until another one is set.
+    pub fn get_cancel_token(&self) -> limit::CancelToken {
+        self.ctx.cancel_token.clone()
+    }
+
+    /// Apply `op_v` in order, rewriting each path to the single step that the write needs.
+    fn write_op_v<'a, 'f>(
+        &'a mut self,
+        op_v: Vec<WriteOp>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        Box::pin(async move {
+            let mut global_op_v = Vec::new();
+            for op in op_v {
+                let mut path = op.path().clone();
+                let step = match path.step_v.pop() {
+                    Some(step) => step,
+                    None => {
+                        if let WriteOp::DeleteAll(_) = op {
+                            global_op_v.push(op);
+                        }
+                        continue;
                     }
+                };
+                if !step.is_plain() {
+                    return Err(moon_err::Error::new(
+                        err::ErrorKind::RuntimeError,
+                        format!("can not write wildcard, repeated, indexed or filtered step"),
+                        format!("at write_batch"),
+                    ));
+                }
+                let root_v = if path.step_v.is_empty() {
+                    path.root_v
+                } else {
+                    // the read must see the writes before it
+                    self.global
+                        .write_batch(std::mem::take(&mut global_op_v))
+                        .await?;
+                    self.get(&path).await?
+                };
+                let path = Path {
+                    root_v,
+                    step_v: vec![step],
+                };
+                let op = match op {
+                    WriteOp::Set(_, item_v) => WriteOp::Set(path, item_v),
+                    WriteOp::Append(_, item_v) => WriteOp::Append(path, item_v),
+                    WriteOp::Delete(_, item_v) => WriteOp::Delete(path, item_v),
+                    WriteOp::DeleteAll(_) => WriteOp::DeleteAll(path),
+                };
+                if op.path().is_temp() {
+                    self.temp.write_batch(vec![op]).await?;
+                } else {
+                    global_op_v.push(op);
                 }
-                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
             }
+            self.global.write_batch(global_op_v).await
+        })
+    }
+
+    /// Engine for a function stored in the graph, sharing the global, the registry, the limit, the
+    /// observer and the script cache.
+    fn new_sub_engine(&mut self) -> err::Result<EdgeEngine<'_, DM>> {
+        let ctx = self.ctx.enter()?;
+        let mut sub_engine = EdgeEngine::new(&mut *self.global);
+        sub_engine.registry = self.registry.clone();
+        sub_engine.ctx = ctx;
+        sub_engine.observer = self.observer.clone();
+        sub_engine.cache = self.cache.clone();
+        Ok(sub_engine)
+    }
+
+    pub fn while1<'a, 'a1, 'f>(
+        &'a self,
+        path: &'a1 Path,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(async move {
+            self.wait(path, false, None).await?;
+            Ok(())
         })
     }
 
@@ -245,6 +807,33 @@ where
         'a1: 'f,
     {
         Box::pin(async move {
+            self.wait(path, true, None).await?;
+            Ok(())
+        })
+    }
+
+    /// # Wait until the targets of `path` are empty or not.
+    ///
+    /// Woken by the changes of the watched edges if the data manager can notify, otherwise
+    /// polling. Returns `false` if `timeout` elapsed first.
+    pub fn wait<'a, 'a1, 'f>(
+        &'a self,
+        path: &'a1 Path,
+        empty: bool,
+        timeout: Option<Duration>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<bool>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(async move {
+            if path.step_v.is_empty() {
+                return Err(moon_err::Error::new(
+                    err::ErrorKind::RuntimeError,
+                    format!("nothing to wait: {}", path.to_string()),
+                    format!("at wait"),
+                ));
+            }
             let mut path = path.clone();
             let step = path.step_v.pop().unwrap();
             let root_v = self.get(&path).await?;
@@ -252,17 +841,48 @@ where
                 root_v,
                 step_v: vec![step],
             };
-            loop {
-                if path.is_temp() {
-                    if self.temp.get(&path).await?.is_empty() {
+            let dm: &dyn AsDataManager = if path.is_temp() {
+                &self.temp
+            } else {
+                &*self.global
+            };
+
+            let wait = async {
+                // subscribe before checking, so a change between them is not missed
+                let mut receiver = dm.subscribe();
+                loop {
+                    if dm.get(&path).await?.is_empty() == empty {
                         return Ok(());
                     }
-                } else {
-                    if self.global.get(&path).await?.is_empty() {
-                        return Ok(());
+                    match &mut receiver {
+                        Some(receiver) => loop {
+                            match receiver.recv().await {
+                                Ok(change) => {
+                                    if dep::is_watched(&path, &change) {
+                                        break;
+                                    }
+                                }
+                                Err(broadcast::error::RecvError::Lagged(_)) => break,
+                                Err(broadcast::error::RecvError::Closed) => {
+                                    return Err(moon_err::Error::new(
+                                        err::ErrorKind::RuntimeError,
+                                        format!("notifier closed"),
+                                        format!("at wait"),
+                                    ));
+                                }
+                            }
+                        },
+                        None => tokio::time::sleep(Duration::from_millis(10)).await,
                     }
                 }
-                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            };
+
+            match timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, self.ctx.guard(wait)).await {
+                    Ok(rs) => rs?.map(|_| true),
+                    Err(_) => Ok(false),
+                },
+                None => self.ctx.guard(wait).await?.map(|_| true),
             }
         })
     }
@@ -329,10 +949,10 @@ where
         if path.step_v.is_empty() {
             return Box::pin(future::ready(Ok(())));
         }
-        if path.step_v.last().unwrap().arrow != "->" {
+        if !path.step_v.last().unwrap().is_plain() {
             return Box::pin(future::ready(Err(moon_err::Error::new(
                 err::ErrorKind::RuntimeError,
-                format!("can not set parents"),
+                format!("can not set wildcard, repeated, indexed or filtered step"),
                 format!("at append"),
             ))));
         }
@@ -380,10 +1000,10 @@ where
             return Box::pin(future::ready(Ok(())));
         }
 
-        if path.step_v.last().unwrap().arrow != "->" {
+        if !path.step_v.last().unwrap().is_plain() {
             return Box::pin(future::ready(Err(moon_err::Error::new(
                 err::ErrorKind::RuntimeError,
-                format!("can not set parents"),
+                format!("can not set wildcard, repeated, indexed or filtered step"),
                 format!("at set"),
             ))));
         }
@@ -422,6 +1042,82 @@ where
         })
     }
 
+    fn delete<'a, 'a1, 'f>(
+        &'a mut self,
+        path: &'a1 Path,
+        item_v: Vec<String>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        if path.step_v.is_empty() {
+            return Box::pin(future::ready(Ok(())));
+        }
+        if !path.step_v.last().unwrap().is_plain() {
+            return Box::pin(future::ready(Err(moon_err::Error::new(
+                err::ErrorKind::RuntimeError,
+                format!("can not delete wildcard, repeated, indexed or filtered step"),
+                format!("at delete"),
+            ))));
+        }
+        let mut path = path.clone();
+        Box::pin(async move {
+            let step = path.step_v.pop().unwrap();
+            let root_v = self.get(&path).await?;
+            let path = Path {
+                root_v,
+                step_v: vec![step],
+            };
+            if path.is_temp() {
+                self.temp.delete(&path, item_v).await
+            } else {
+                self.global.delete(&path, item_v).await
+            }
+        })
+    }
+
+    fn delete_all<'a, 'a1, 'f>(
+        &'a mut self,
+        path: &'a1 Path,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        if !path.step_v.is_empty() {
+            return self.set(path, Vec::new());
+        }
+        self.global.delete_all(path)
+    }
+
+    /// Writes to the global are committed by as few `write_batch` of it as the reads between
+    /// them allow, all in one transaction of the global as the default does.
+    fn write_batch<'a, 'f>(
+        &'a mut self,
+        op_v: Vec<WriteOp>,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        Box::pin(async move {
+            let is_own = data::begin_batch(&mut *self.global).await?;
+            let mut rs = self.temp.begin().await;
+            let is_temp_begun = rs.is_ok();
+            if is_temp_begun {
+                rs = self.write_op_v(op_v).await;
+            }
+            // the global first, so the temp is rolled back if its commit fails
+            if is_own {
+                rs = data::end_batch(&mut *self.global, rs).await;
+            }
+            if is_temp_begun {
+                rs = data::end_batch(&mut self.temp, rs).await;
+            }
+            rs
+        })
+    }
+
     fn get<'a, 'a1, 'f>(
         &'a self,
         path: &'a1 Path,
@@ -459,6 +1155,31 @@ where
         })
     }
 
+    fn subscribe(&self) -> Option<broadcast::Receiver<Change>> {
+        self.global.subscribe()
+    }
+
+    fn begin<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        self.global.begin()
+    }
+
+    fn commit<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        self.global.commit()
+    }
+
+    fn rollback<'a, 'f>(&'a mut self) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+    {
+        self.global.rollback()
+    }
+
     fn call_and_return<'a, 'a1, 'a2, 'a3, 'f>(
         &'a mut self,
         func: &'a1 str,
@@ -474,13 +1195,13 @@ where
         Box::pin(async move {
             match func {
                 // while
-                "while0" => {
-                    self.while0(input).await?;
-                    Ok(vec![])
-                }
-                "while1" => {
-                    self.while1(input).await?;
-                    Ok(vec![])
+                "while0" | "while1" => {
+                    let timeout = dep::parse_timeout(&self.get(input1).await?)?;
+                    if self.wait(input, func == "while0", timeout).await? {
+                        Ok(vec![])
+                    } else {
+                        Ok(vec![format!("timeout")])
+                    }
                 }
                 "get_code_v" => {
                     let root_v = self.get(input).await?;
@@ -520,6 +1241,21 @@ where
             let input = self.temp_2_global(input).await?;
             let input1 = self.temp_2_global(input1).await?;
 
+            match self.registry.lookup(func) {
+                registry::Lookup::Native(function) => {
+                    return function.call(self, output, &input, &input1).await;
+                }
+                registry::Lookup::Disabled => {
+                    // not NotFound, which would run `func` as a script in the graph
+                    return Err(moon_err::Error::new(
+                        err::ErrorKind::PermissionDenied,
+                        format!("function disabled: {func}"),
+                        format!("at call"),
+                    ));
+                }
+                registry::Lookup::Builtin => (),
+            }
+
             if !output.is_temp() {
                 let g_output = self.temp_2_global(output).await?;
 
@@ -561,6 +1297,8 @@ where
                 "sum" => func::sum(self, output, &input, &input1).await,
                 //
                 "=" => func::set(self, output, &input, &input1).await,
+                "remove" => func::remove(self, output, &input, &input1).await,
+                "clear" => func::clear(self, output, &input, &input1).await,
                 //
                 "slice" => func::slice(self, output, &input, &input1).await,
                 "sort" => func::sort(self, output, &input, &input1).await,
@@ -575,9 +1313,33 @@ where
     }
 }
 
+/// A lowered script, executed by [EdgeEngine].
+#[derive(Clone, Debug)]
+pub enum Code {
+    Inc(Inc),
+    If {
+        cond: Path,
+        then_v: Vec<Code>,
+        else_v: Vec<Code>,
+    },
+    While {
+        /// Evaluate `cond` before every test.
+        cond_v: Vec<Code>,
+        cond: Path,
+        body_v: Vec<Code>,
+    },
+    For {
+        item: Path,
+        path: Path,
+        body_v: Vec<Code>,
+    },
+}
+
 #[derive(Clone, Debug)]
 pub struct Inc {
     pub output: Path,
+    /// Outputs bound to the named return values of a script function.
+    pub ret_v: Vec<Path>,
     pub function: Path,
     pub input: Path,
     pub input1: Path,
@@ -591,129 +1353,773 @@ mod tests {
         Path,
     };
 
-    #[test]
-    fn test() {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test() {
+        let mut dm = MemDataManager::new(None);
+
+        let mut engine = EdgeEngine::new(&mut dm);
+
+        engine
+            .execute_script(&vec![
+                "$->$:temp append $->$:temp '$->$:output\\s+\\s1\\s1'".to_string(),
+                "test->test:test = $->$:temp _".to_string(),
+            ])
+            .await
             .unwrap();
-        rt.block_on(async {
-            let mut dm = MemDataManager::new(None);
 
-            let mut engine = EdgeEngine::new(&mut dm);
+        let rs = engine
+            .get(&Path::from_str("test->test:test"))
+            .await
+            .unwrap();
 
-            engine
-                .execute_script(&vec![
-                    "$->$:temp append $->$:temp '$->$:output\\s+\\s1\\s1'".to_string(),
-                    "test->test:test = $->$:temp _".to_string(),
-                ])
-                .await
-                .unwrap();
+        assert_eq!(rs.len(), 1);
+    }
 
-            let rs = engine
-                .get(&Path::from_str("test->test:test"))
-                .await
-                .unwrap();
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_string() {
+        let mut dm = MemDataManager::new(None);
 
-            assert_eq!(rs.len(), 1);
-        });
+        let mut engine = EdgeEngine::new(&mut dm);
+
+        let rs = engine
+            .execute_script(&vec![
+                "$->$:output append $->$:temp 'running\\s=>\\s智\\s明'".to_string(),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(rs.len(), 1);
+        assert_eq!(rs[0], "running => 智 明");
     }
 
-    #[test]
-    fn test_string() {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dump() {
+        // dm
+        let mut dm = MemDataManager::new(None);
+
+        // engine
+        let mut engine = EdgeEngine::new(&mut dm);
+
+        // data
+        engine
+            .execute_script(&vec![
+                //
+                format!("test->test:step1 = ? _"),
+                //
+                format!("test->test:step1->test:step2 = test1 _"),
+            ])
+            .await
             .unwrap();
-        rt.block_on(async {
-            let mut dm = MemDataManager::new(None);
 
-            let mut engine = EdgeEngine::new(&mut dm);
+        // rs
+        let rs = engine
+            .execute_script(&vec![format!("$->$:output dump test test")])
+            .await
+            .unwrap();
 
-            let rs = engine
-                .execute_script(&vec![
-                    "$->$:output append $->$:temp 'running\\s=>\\s智\\s明'".to_string(),
-                ])
-                .await
-                .unwrap();
+        // rj
+        let rj = json::parse(&crate::util::rs_2_str(&rs)).unwrap();
 
-            assert_eq!(rs.len(), 1);
-            assert_eq!(rs[0], "running => 智 明");
-        });
+        // assert
+        assert_eq!(rj[0]["test:step1"][0]["test:step2"][0], "test1");
     }
 
-    #[test]
-    fn test_dump() {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_load() {
+        // dm
+        let mut dm = MemDataManager::new(None);
+
+        // engine
+        let mut engine = EdgeEngine::new(&mut dm);
+
+        engine
+            .load(
+                &json::object! {
+                    "$:test": "test"
+                },
+                &Path::from_str("$->$:test"),
+            )
+            .await
             .unwrap();
-        rt.block_on(async {
-            // dm
-            let mut dm = MemDataManager::new(None);
 
-            // engine
-            let mut engine = EdgeEngine::new(&mut dm);
+        // rs
+        let rs = engine
+            .execute_script(&vec![format!("$->$:output dump $->$:test $")])
+            .await
+            .unwrap();
 
-            // data
-            engine
-                .execute_script(&vec![
-                    //
-                    format!("test->test:step1 = ? _"),
-                    //
-                    format!("test->test:step1->test:step2 = test1 _"),
-                ])
-                .await
-                .unwrap();
+        // rj
+        let rj = json::parse(&crate::util::rs_2_str(&rs)).unwrap();
 
-            // rs
-            let rs = engine
-                .execute_script(&vec![format!("$->$:output dump test test")])
-                .await
-                .unwrap();
+        // assert
+        assert_eq!(rj[0]["$:test"][0], "test");
+
+        engine
+            .load(
+                &json::object! {
+                    "user": [{ "name": "a" }, { "name": "b" }]
+                },
+                &Path::from_str("root->data"),
+            )
+            .await
+            .unwrap();
+        engine
+            .load(
+                &json::object! {
+                    "user": { "name": "c" }
+                },
+                &Path::from_str("root->data"),
+            )
+            .await
+            .unwrap();
+        let rs = engine
+            .get(&Path::from_str("root->data->user->name"))
+            .await
+            .unwrap();
+        assert_eq!(rs, ["a", "b", "c"]);
+
+        engine
+            .load(
+                &json::object! {
+                    "tags*": "a", "a{2}": "b", "item#1": "c", "x[y]": "d", "p:q:r": "e"
+                },
+                &Path::from_str("root->plain"),
+            )
+            .await
+            .unwrap();
+        let node = engine.get(&Path::from_str("root->plain")).await.unwrap();
+        let rs = engine.get_code_v(&node[0], "").await.unwrap();
+        assert_eq!(rs, ["tags*", "a{2}", "item#1", "x[y]"]);
+        let rs = engine.get_code_v(&node[0], "p").await.unwrap();
+        assert_eq!(rs, ["q:r"]);
+    }
+}
 
-            // rj
-            let rj = json::parse(&crate::util::rs_2_str(&rs)).unwrap();
+#[cfg(test)]
+mod test_registry {
+    use crate::{
+        err,
+        util::{
+            data::MemDataManager,
+            engine::{AsEdgeEngine, EdgeEngine},
+        },
+    };
 
-            // assert
-            assert_eq!(rj[0]["test:step1"][0]["test:step2"][0], "test1");
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_registry() {
+        let mut dm = MemDataManager::new(None);
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        engine.get_registry_mut().register_fn(
+            "concat",
+            &["left", "right"],
+            |left, right| async move { Ok(vec![format!("{}{}", left.join(""), right.join(""))]) },
+        );
+        engine.get_registry_mut().disable("rand");
+
+        let rs = engine
+            .execute_script(&vec![format!("$->$:output concat a b")])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["ab".to_string()]);
+
+        let e = engine
+            .execute_script(&vec![format!("$->$:output rand 1 _")])
+            .await
+            .unwrap_err();
+        assert!(matches!(e.first().0, err::ErrorKind::PermissionDenied));
+        assert_eq!(e.first().1, "function disabled: rand");
+    }
+}
+
+#[cfg(test)]
+mod test_limit {
+    use crate::{
+        err,
+        util::{
+            data::{AsDataManager, MemDataManager},
+            engine::{
+                limit::{CancelToken, Limit},
+                AsEdgeEngine, EdgeEngine,
+            },
+            Path,
+        },
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_limit() {
+        let mut dm = MemDataManager::new(None);
+
+        // a function calling itself
+        dm.set(
+            &Path::from_str("root->fn:loop"),
+            vec![format!("$->$:output root->fn:loop _ _")],
+        )
+        .await
+        .unwrap();
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        engine.set_limit(Limit {
+            max_depth: Some(8),
+            ..Default::default()
+        });
+        let e = engine
+            .execute_script(&vec![format!("$->$:output root->fn:loop _ _")])
+            .await
+            .unwrap_err();
+        assert!(matches!(e.first().0, err::ErrorKind::Interrupted));
+
+        engine.set_limit(Limit {
+            max_inc: Some(2),
+            ..Default::default()
         });
+        let e = engine
+            .execute_script(&vec![
+                format!("$->$:a = 1 _"),
+                format!("$->$:b = 2 _"),
+                format!("$->$:c = 3 _"),
+            ])
+            .await
+            .unwrap_err();
+        assert!(matches!(e.first().0, err::ErrorKind::Interrupted));
+
+        engine.set_limit(Limit {
+            timeout: Some(std::time::Duration::from_millis(50)),
+            ..Default::default()
+        });
+        let e = engine
+            .execute_script(&vec![format!("_ while1 root->test:never _")])
+            .await
+            .unwrap_err();
+        assert!(matches!(e.first().0, err::ErrorKind::Interrupted));
+
+        engine.set_limit(Limit::default());
+        engine.get_cancel_token().cancel();
+        assert!(engine
+            .execute_script(&vec![format!("$->$:a = 1 _")])
+            .await
+            .is_err());
+        // still cancelled for the next script
+        let e = engine
+            .execute_script(&vec![format!("$->$:a = 1 _")])
+            .await
+            .unwrap_err();
+        assert!(matches!(e.first().0, err::ErrorKind::Interrupted));
+
+        engine.set_cancel_token(CancelToken::new());
+        engine
+            .execute_script(&vec![format!("$->$:a = 1 _")])
+            .await
+            .unwrap();
     }
+}
 
-    #[test]
-    fn test_load() {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
+#[cfg(test)]
+mod test_wait {
+    use crate::util::{
+        data::MemDataManager,
+        engine::{AsEdgeEngine, EdgeEngine},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_wait_timeout() {
+        let mut dm = MemDataManager::new(None);
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let rs = engine
+            .execute_script(&vec![format!("$->$:output while1 root->test:never 20")])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["timeout".to_string()]);
+
+        let rs = engine
+            .execute_script(&vec![format!("$->$:output while0 root->test:never 20")])
+            .await
+            .unwrap();
+        assert!(rs.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod test_transaction {
+    use crate::util::{
+        data::{AsDataManager, MemDataManager},
+        engine::{AsEdgeEngine, EdgeEngine},
+        Path,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_transactional() {
+        let mut dm = MemDataManager::new(None);
+
+        dm.set(
+            &Path::from_str("root->fn:fail"),
+            vec![
+                format!("root->test:b = 2 _"),
+                format!("$->$:output new abc 1"),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        engine.set_transactional(true);
+        assert!(engine
+            .execute_script(&vec![
+                format!("root->test:a = 1 _"),
+                format!("$->$:output root->fn:fail _ _"),
+            ])
+            .await
+            .is_err());
+        assert!(engine
+            .get(&Path::from_str("root->test:a"))
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(engine
+            .get(&Path::from_str("root->test:b"))
+            .await
+            .unwrap()
+            .is_empty());
+
+        engine
+            .execute_script(&vec![format!("root->test:a = 1 _")])
+            .await
+            .unwrap();
+        assert_eq!(
+            engine.get(&Path::from_str("root->test:a")).await.unwrap(),
+            ["1"]
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_write_batch {
+    use crate::util::{
+        data::{AsDataManager, MemDataManager, WriteOp},
+        engine::EdgeEngine,
+        Path,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_write_batch_failed() {
+        let mut dm = MemDataManager::new(None);
+        let mut engine = EdgeEngine::new(&mut dm);
+        let op_v = vec![
+            WriteOp::Set(Path::from_str("root->test:a"), vec![format!("1")]),
+            WriteOp::Set(Path::from_str("$->$:tmp"), vec![format!("1")]),
+            WriteOp::Set(Path::from_str("root->test:*"), vec![format!("1")]),
+        ];
+        assert!(engine.write_batch(op_v).await.is_err());
+        assert!(engine
+            .get(&Path::from_str("root->test:a"))
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(engine
+            .get(&Path::from_str("$->$:tmp"))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // the temp is not left in a transaction
+        engine
+            .write_batch(vec![WriteOp::Set(
+                Path::from_str("$->$:tmp"),
+                vec![format!("1")],
+            )])
+            .await
             .unwrap();
-        rt.block_on(async {
-            // dm
-            let mut dm = MemDataManager::new(None);
 
-            // engine
-            let mut engine = EdgeEngine::new(&mut dm);
+        // the global is rolled back when the temp can not begin
+        let mut temp = MemDataManager::new(None);
+        temp.begin().await.unwrap();
+        let mut engine = EdgeEngine::new_with_temp(&mut dm, temp);
+        assert!(engine
+            .write_batch(vec![WriteOp::Set(
+                Path::from_str("root->test:b"),
+                vec![format!("1")],
+            )])
+            .await
+            .is_err());
+        drop(engine);
+        dm.begin().await.unwrap();
+        assert!(dm
+            .get(&Path::from_str("root->test:b"))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
+
+#[cfg(test)]
+mod test_trace {
+    use std::sync::Arc;
+
+    use crate::util::{
+        data::{AsDataManager, MemDataManager},
+        engine::{trace::Recorder, AsEdgeEngine, EdgeEngine},
+        Path,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_trace() {
+        let mut dm = MemDataManager::new(None);
+        dm.set(
+            &Path::from_str("root->fn:inc"),
+            vec![format!("$->$:output + $->$:input 1")],
+        )
+        .await
+        .unwrap();
+
+        let recorder = Arc::new(Recorder::new());
+        let mut engine = EdgeEngine::new(&mut dm);
+        engine.set_observer(recorder.clone());
+        let rs = engine
+            .execute_script(&vec![
+                format!("$->$:a = + 1 1"),
+                format!("$->$:output = root->fn:inc $->$:a _"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["3".to_string()]);
+
+        let record_v = recorder.record_v();
+        assert_eq!(record_v.len(), 3);
+        // the called script is finished first
+        assert_eq!(record_v[0].event.depth, 0);
+        assert_eq!(record_v[0].rs.output_item_v, vec!["2".to_string()]);
+        assert_eq!(record_v[1].event.depth, 1);
+        assert_eq!(record_v[1].event.function, "+");
+        assert_eq!(record_v[1].event.input_item_v, vec!["2".to_string()]);
+        assert_eq!(record_v[2].event.index, 1);
+        assert_eq!(record_v[2].rs.output_item_v, vec!["3".to_string()]);
+        assert_eq!(recorder.to_string().lines().count(), 3);
+    }
+}
+
+#[cfg(test)]
+mod test_script_cache {
+    use crate::util::{
+        data::{AsDataManager, MemDataManager},
+        engine::{AsEdgeEngine, EdgeEngine},
+        Path,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_script_cache() {
+        let mut dm = MemDataManager::new(None);
+        dm.set(
+            &Path::from_str("root->fn:inc"),
+            vec![format!("$->$:output + $->$:input 1")],
+        )
+        .await
+        .unwrap();
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let script = vec![
+            format!("$->$:a = root->fn:inc 1 _"),
+            format!("$->$:output = root->fn:inc $->$:a _"),
+        ];
+        let rs = engine.execute_script(&script).await.unwrap();
+        assert_eq!(rs, vec!["3".to_string()]);
+        assert_eq!(engine.get_script_cache().miss_cnt(), 1);
+        assert_eq!(engine.get_script_cache().hit_cnt(), 1);
+
+        // the edited function is parsed again
+        engine
+            .get_global_mut()
+            .set(
+                &Path::from_str("root->fn:inc"),
+                vec![format!("$->$:output + $->$:input 2")],
+            )
+            .await
+            .unwrap();
+        engine.reset_temp();
+        let rs = engine.execute_script(&script).await.unwrap();
+        assert_eq!(rs, vec!["5".to_string()]);
+        assert_eq!(engine.get_script_cache().miss_cnt(), 2);
+        assert_eq!(engine.get_script_cache().hit_cnt(), 2);
+        // the stale entry is kept until evicted
+        assert_eq!(engine.get_script_cache().len(), 2);
+    }
+}
+
+#[cfg(test)]
+mod test_escape {
+    use crate::util::{
+        data::{AsDataManager, MemDataManager},
+        engine::{AsEdgeEngine, EdgeEngine},
+        Path,
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_escaped_code() {
+        let mut dm = MemDataManager::new(None);
+        for (code, value) in [("item#1", "a"), ("a{2}", "b"), ("x*", "c"), ("a[b]", "d")] {
+            dm.set(
+                &Path::root("root").fwd("list", code),
+                vec![format!("{value}")],
+            )
+            .await
+            .unwrap();
+        }
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let rs = engine
+            .execute_script(&vec![
+                format!("$->$:output = root->list:item\\#1 _"),
+                format!("$->$:output += = root->list:a\\{{2\\}} _"),
+                format!("$->$:output += = root->list:x\\* _"),
+                format!("$->$:output += = root->list:a\\[b\\] _"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["a", "b", "c", "d"]);
+    }
+}
+
+#[cfg(test)]
+mod test_ret {
+    use crate::{
+        err,
+        util::{
+            data::{AsDataManager, MemDataManager},
+            engine::{AsEdgeEngine, EdgeEngine},
+            Path,
+        },
+    };
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_ret() {
+        let mut dm = MemDataManager::new(None);
+        dm.set(
+            &Path::from_str("root->fn:div"),
+            vec![
+                format!("$->$:ret = quotient _"),
+                format!("$->$:ret += = rest _"),
+                format!("$->$:output = / $->$:input $->$:input1"),
+                format!("$->$:quotient = $->$:output _"),
+                format!("$->$:rest = % $->$:input $->$:input1"),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let rs = engine
+            .execute_script(&vec![
+                format!("$->$:a $->$:b $->$:c $->$:d = root->fn:div 7 2"),
+                format!("$->$:output = $->$:a _"),
+                format!("$->$:output += = $->$:b _"),
+                format!("$->$:output += = $->$:c _"),
+                format!("$->$:output += = $->$:d _"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["3.5", "3.5", "1"]);
+
+        for name in ["input", "__expr0"] {
             engine
-                .load(
-                    &json::object! {
-                        "$:test": "test"
-                    },
-                    &Path::from_str("$->$:test"),
+                .set(
+                    &Path::from_str("root->fn:clash"),
+                    vec![format!("$->$:ret = {name} _")],
                 )
                 .await
                 .unwrap();
+            let e = engine
+                .execute_script(&vec![format!("$->$:a $->$:b = root->fn:clash 1 _")])
+                .await
+                .unwrap_err();
+            assert!(matches!(e.first().0, err::ErrorKind::RuntimeError));
+            assert_eq!(e.first().1, format!("reserved name: {name}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_expr {
+    use crate::util::{
+        data::MemDataManager,
+        engine::{AsEdgeEngine, EdgeEngine},
+    };
 
-            // rs
-            let rs = engine
-                .execute_script(&vec![format!("$->$:output dump $->$:test $")])
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_expr() {
+        let mut dm = MemDataManager::new(None);
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let rs = engine
+            .execute_script(&vec![
+                format!("$->$:b = 3 _"),
+                format!("$->$:output = + 1 (* (- $->$:b 1) 4)"),
+                format!("$->$:output += = (count $->$:output _) _"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["9", "1"]);
+    }
+}
+
+#[cfg(test)]
+mod test_block {
+    use crate::util::{
+        data::MemDataManager,
+        engine::{AsEdgeEngine, EdgeEngine},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_block() {
+        let mut dm = MemDataManager::new(None);
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let rs = engine
+            .execute_script(&vec![
+                format!("$->$:i = 0 _"),
+                format!("while (< $->$:i 4) {{"),
+                format!("    $->$:i = + $->$:i 1"),
+                format!("    $->$:i_v += = $->$:i _"),
+                format!("}}"),
+                format!("for $->$:item in $->$:i_v {{"),
+                format!("    if (== (% $->$:item 2) 0) {{"),
+                format!("        $->$:output += = $->$:item _"),
+                format!("    }} else {{"),
+                format!("        $->$:output += = odd _"),
+                format!("    }}"),
+                format!("}}"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["odd", "2", "odd", "4"]);
+    }
+}
+
+#[cfg(test)]
+mod test_index {
+    use crate::util::{
+        data::MemDataManager,
+        engine::{AsEdgeEngine, EdgeEngine},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_index() {
+        let mut dm = MemDataManager::new(None);
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let rs = engine
+            .execute_script(&vec![
+                format!("$->$:list append $->$:list a # a list of a, b, c"),
+                format!("$->$:list append $->$:list b"),
+                format!("$->$:list append $->$:list c"),
+                format!("l->list:item = = $->$:list _"),
+                format!("$->$:output = = l->list:item#-1 _"),
+                format!("$->$:output += = l->list:item#0..2 _"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["c", "a", "b"]);
+    }
+}
+
+#[cfg(test)]
+mod test_param {
+    use std::collections::HashMap;
+
+    use crate::{
+        err,
+        util::{
+            data::{AsDataManager, MemDataManager},
+            engine::{AsEdgeEngine, EdgeEngine},
+            Path,
+        },
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_param() {
+        let mut dm = MemDataManager::new(None);
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let mut param_mp = HashMap::new();
+        param_mp.insert(format!("user"), vec![format!("bob's id->x")]);
+        param_mp.insert(format!("name_v"), vec![format!("bob b"), format!("it's")]);
+        let rs = engine
+            .execute_script_with(
+                &vec![
+                    format!(":user->app:name = = :name_v _"),
+                    format!("$->$:output = = :user->app:name _"),
+                    format!("$->$:output += = ':user' _"),
+                ],
+                &param_mp,
+            )
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["bob b", "it's", ":user"]);
+        assert_eq!(
+            dm.get(&Path::root("bob's id->x").fwd("app", "name"))
                 .await
-                .unwrap();
+                .unwrap(),
+            vec!["bob b", "it's"]
+        );
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let e = engine
+            .execute_script_with(&vec![format!("$->$:output = = :nobody _")], &param_mp)
+            .await
+            .unwrap_err();
+        assert!(matches!(e.first().0, err::ErrorKind::NotFound));
+    }
+}
 
-            // rj
-            let rj = json::parse(&crate::util::rs_2_str(&rs)).unwrap();
+#[cfg(test)]
+mod test_remove {
+    use crate::util::{
+        data::MemDataManager,
+        engine::{AsEdgeEngine, EdgeEngine},
+    };
 
-            // assert
-            assert_eq!(rj[0]["$:test"][0], "test");
-        })
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remove() {
+        let mut dm = MemDataManager::new(None);
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let rs = engine
+            .execute_script(&vec![
+                format!("$->$:list = 1 _"),
+                format!("$->$:list += = 2 _"),
+                format!("$->$:list += = 3 _"),
+                format!("$->$:list remove 2 _"),
+                format!("$->$:output = = $->$:list _"),
+                format!("root->list:item = = $->$:list _"),
+                format!("root->list:item clear _ _"),
+                format!("$->$:output += = root->list:item _"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["1", "3"]);
+    }
+}
+
+#[cfg(test)]
+mod test_set_back {
+    use crate::util::{
+        data::MemDataManager,
+        engine::{AsEdgeEngine, EdgeEngine},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_set_back() {
+        let mut dm = MemDataManager::new(None);
+
+        let mut engine = EdgeEngine::new(&mut dm);
+        let rs = engine
+            .execute_script(&vec![
+                format!("x<-tree:child = a _"),
+                format!("x<-tree:child += = b _"),
+                format!("$->$:output = = a->tree:child _"),
+                format!("$->$:output += = x<-tree:child _"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(rs, vec!["x", "a", "b"]);
     }
 }
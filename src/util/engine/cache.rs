@@ -0,0 +1,128 @@
+//! Parsed scripts of the functions stored in the graph.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::err;
+
+use super::{dep, Code};
+
+const DEFAULT_CAPACITY: usize = 256;
+
+struct Entry {
+    code_v: Arc<Vec<Code>>,
+    last_used: u64,
+}
+
+/// # Cache of parsed scripts, keyed by the text of the script.
+///
+/// Changes of the graph do not invalidate it, the key does: a function edited in the graph has a
+/// new text, so it is parsed again and the stale entry is never used, only evicted as the least
+/// recently used one when the cache is full. Only the functions called by a script are cached,
+/// the script given to `execute_script` is parsed on every call.
+pub struct ScriptCache {
+    capacity: usize,
+    entry_mp: Mutex<HashMap<Vec<String>, Entry>>,
+    hit_cnt: AtomicU64,
+    miss_cnt: AtomicU64,
+}
+
+impl Default for ScriptCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl ScriptCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entry_mp: Mutex::new(HashMap::new()),
+            hit_cnt: AtomicU64::new(0),
+            miss_cnt: AtomicU64::new(0),
+        }
+    }
+
+    pub fn hit_cnt(&self) -> u64 {
+        self.hit_cnt.load(Ordering::Relaxed)
+    }
+
+    pub fn miss_cnt(&self) -> u64 {
+        self.miss_cnt.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entry_mp.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.entry_mp.lock().unwrap().clear();
+    }
+
+    pub(crate) fn parse(&self, script: &[String]) -> err::Result<Arc<Vec<Code>>> {
+        let used = self.hit_cnt.load(Ordering::Relaxed) + self.miss_cnt.load(Ordering::Relaxed);
+        if let Some(entry) = self.entry_mp.lock().unwrap().get_mut(script) {
+            self.hit_cnt.fetch_add(1, Ordering::Relaxed);
+            entry.last_used = used;
+            return Ok(entry.code_v.clone());
+        }
+
+        self.miss_cnt.fetch_add(1, Ordering::Relaxed);
+        let code_v = Arc::new(dep::parse_script1(script)?);
+        if self.capacity == 0 {
+            return Ok(code_v);
+        }
+
+        let mut entry_mp = self.entry_mp.lock().unwrap();
+        if entry_mp.len() >= self.capacity {
+            let lru = entry_mp
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(script, _)| script.clone())
+                .unwrap();
+            entry_mp.remove(&lru);
+        }
+        entry_mp.insert(
+            script.to_vec(),
+            Entry {
+                code_v: code_v.clone(),
+                last_used: used,
+            },
+        );
+        Ok(code_v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScriptCache;
+
+    #[test]
+    fn should_evict_lru() {
+        let cache = ScriptCache::new(2);
+        let a = vec![format!("$->$:output = a _")];
+        let b = vec![format!("$->$:output = b _")];
+        let c = vec![format!("$->$:output = c _")];
+
+        cache.parse(&a).unwrap();
+        cache.parse(&b).unwrap();
+        cache.parse(&a).unwrap();
+        cache.parse(&c).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!((cache.hit_cnt(), cache.miss_cnt()), (1, 3));
+
+        // b was evicted
+        cache.parse(&a).unwrap();
+        cache.parse(&b).unwrap();
+        assert_eq!((cache.hit_cnt(), cache.miss_cnt()), (2, 4));
+    }
+}
@@ -0,0 +1,166 @@
+//! Limits of running scripts in an [EdgeEngine](super::EdgeEngine).
+
+use std::{
+    future::{self, Future},
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+use crate::err;
+
+/// `None` means unlimited.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Limit {
+    /// Instructions executed by a script, including the ones of called scripts and the tests of
+    /// loops.
+    pub max_inc: Option<u64>,
+    /// Depth of nested scripts called as functions.
+    pub max_depth: Option<usize>,
+    /// Wall-clock time of a script, including the waiting of `while0` and `while1`.
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct Cancellation {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// Cancel the scripts running with this token, from any thread.
+///
+/// A cancellation is permanent: every later script with this token fails as `Interrupted` at
+/// once. Give the engine a new token by `set_cancel_token` to run scripts again.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    inner: Arc<Cancellation>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Completes when the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut notified = pin!(self.inner.notify.notified());
+        // register before checking, so a cancellation between them is not missed
+        notified.as_mut().enable();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// State of one top-level script, shared by the engines of the scripts it calls.
+#[derive(Clone, Default)]
+pub(crate) struct Context {
+    pub limit: Limit,
+    pub cancel_token: CancelToken,
+    depth: usize,
+    inc_cnt: Arc<AtomicU64>,
+    deadline: Option<Instant>,
+}
+
+impl Context {
+    pub fn is_top(&self) -> bool {
+        self.depth == 0
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Reset the counters, called when a top-level script starts.
+    pub fn start(&mut self) {
+        self.inc_cnt = Arc::new(AtomicU64::new(0));
+        self.deadline = self.limit.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// Context of a called script.
+    pub fn enter(&self) -> err::Result<Self> {
+        let depth = self.depth + 1;
+        if let Some(max_depth) = self.limit.max_depth {
+            if depth > max_depth {
+                return Err(interrupted(format!("call depth exceeds {max_depth}")));
+            }
+        }
+        Ok(Self {
+            depth,
+            ..self.clone()
+        })
+    }
+
+    /// Count an instruction.
+    pub fn step(&self) -> err::Result<()> {
+        let inc_cnt = self.inc_cnt.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(max_inc) = self.limit.max_inc {
+            if inc_cnt > max_inc {
+                return Err(interrupted(format!("instructions exceed {max_inc}")));
+            }
+        }
+        self.check()
+    }
+
+    /// Run `fu` until it completes, the token is cancelled or the deadline passes.
+    pub async fn guard<F>(&self, fu: F) -> err::Result<F::Output>
+    where
+        F: Future,
+    {
+        let mut fu = pin!(fu);
+        let mut cancelled = pin!(self.cancel_token.cancelled());
+        let race = future::poll_fn(|cx| {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Err(interrupted(format!("cancelled"))));
+            }
+            fu.as_mut().poll(cx).map(Ok)
+        });
+        match self.deadline {
+            Some(deadline) => {
+                match tokio::time::timeout_at(tokio::time::Instant::from_std(deadline), race).await
+                {
+                    Ok(rs) => rs,
+                    Err(_) => Err(interrupted(format!("timeout"))),
+                }
+            }
+            None => race.await,
+        }
+    }
+
+    /// Check the cancellation and the deadline.
+    pub fn check(&self) -> err::Result<()> {
+        if self.cancel_token.is_cancelled() {
+            return Err(interrupted(format!("cancelled")));
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(interrupted(format!("timeout")));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn interrupted(message: String) -> moon_err::Error<err::ErrorKind> {
+    moon_err::Error::new(
+        err::ErrorKind::Interrupted,
+        message,
+        format!("at execute_script"),
+    )
+}
@@ -0,0 +1,785 @@
+//! Parser of edge scripts.
+//!
+//! A script is a list of lines, every line holds one statement:
+//!
+//! ```text
+//! output function input input1
+//! output = function input input1
+//! output += function input input1
+//! output ret ret1 = function input input1
+//! ```
+//!
+//! Words are separated by any amount of whitespace, a quoted word may contain whitespace and
+//! `#` starts a comment running to the end of the line.
+//!
+//! Any word but an output may be an expression `(function input input1)`, whose words may be
+//! expressions too:
+//!
+//! ```text
+//! $->$:output = + $->$:a (* $->$:b $->$:c)
+//! ```
+//!
+//! Blocks take whole lines, a condition is true if it has any item:
+//!
+//! ```text
+//! if condition {
+//! } else {
+//! }
+//! while condition {
+//! }
+//! for item in path {
+//! }
+//! ```
+
+use std::fmt::Display;
+
+mod main {
+    use crate::util::Path;
+
+    use super::{Arg, Diagnostic, Expr, Node, Operator, PathLit, Span, Stmt, StrLit};
+
+    pub struct Token {
+        pub text: String,
+        pub span: Span,
+    }
+
+    pub fn tokenize(line: &str, line_no: usize) -> Result<Vec<Token>, Diagnostic> {
+        let mut token_v = Vec::new();
+        let mut char_v = line.char_indices().enumerate().peekable();
+
+        while let Some((column, (start, ch))) = char_v.next() {
+            if ch.is_whitespace() {
+                continue;
+            }
+            if ch == '#' {
+                break;
+            }
+
+            let mut end = start + ch.len_utf8();
+            let mut len = 1;
+            let mut in_quote = ch == '\'';
+            let mut quote_column = column;
+            let mut escaped = ch == '\\';
+            // an expression is one word until its parenthesis is closed
+            let is_expr = ch == '(';
+            let mut depth = if is_expr { 1 } else { 0 };
+            // a filter of a path is one word too
+            let mut bracket_depth = if ch == '[' { 1 } else { 0 };
+            let mut bracket_column = column;
+            while let Some((column, (pos, ch))) = char_v.peek().cloned() {
+                if is_expr && depth == 0 {
+                    break;
+                }
+                if !in_quote && !escaped && depth == 0 && bracket_depth == 0 && ch.is_whitespace() {
+                    break;
+                }
+                char_v.next();
+                end = pos + ch.len_utf8();
+                len += 1;
+                if escaped {
+                    escaped = false;
+                } else if ch == '\\' {
+                    escaped = true;
+                } else if ch == '\'' {
+                    in_quote = !in_quote;
+                    quote_column = column;
+                } else if !in_quote && ch == '[' {
+                    if bracket_depth == 0 {
+                        bracket_column = column;
+                    }
+                    bracket_depth += 1;
+                } else if !in_quote && ch == ']' && bracket_depth > 0 {
+                    bracket_depth -= 1;
+                } else if is_expr && !in_quote && ch == '(' {
+                    depth += 1;
+                } else if is_expr && !in_quote && ch == ')' {
+                    depth -= 1;
+                }
+            }
+            if in_quote {
+                return Err(Diagnostic {
+                    span: Span {
+                        line: line_no,
+                        column: quote_column + 1,
+                        len: 1,
+                    },
+                    message: format!("unterminated quotation"),
+                });
+            }
+            if bracket_depth > 0 {
+                return Err(Diagnostic {
+                    span: Span {
+                        line: line_no,
+                        column: bracket_column + 1,
+                        len: 1,
+                    },
+                    message: format!("unclosed bracket"),
+                });
+            }
+            if depth > 0 {
+                return Err(Diagnostic {
+                    span: Span {
+                        line: line_no,
+                        column: column + 1,
+                        len: 1,
+                    },
+                    message: format!("unclosed parenthesis"),
+                });
+            }
+
+            token_v.push(Token {
+                text: line[start..end].to_string(),
+                span: Span {
+                    line: line_no,
+                    column: column + 1,
+                    len,
+                },
+            });
+        }
+
+        Ok(token_v)
+    }
+
+    pub fn parse_arg(token: Token) -> Result<Arg, Diagnostic> {
+        if token.text.starts_with('(') {
+            return parse_expr(token).map(|expr| Arg::Expr(Box::new(expr)));
+        }
+        Ok(if is_quoted(&token.text) {
+            Arg::Str(StrLit {
+                value: crate::util::escape_word(&token.text),
+                span: token.span,
+            })
+        } else {
+            if let Err((column, message)) = Path::try_parse(&token.text) {
+                return Err(Diagnostic {
+                    span: Span {
+                        line: token.span.line,
+                        column: token.span.column + column - 1,
+                        len: 1,
+                    },
+                    message,
+                });
+            }
+            Arg::Path(PathLit {
+                text: token.text,
+                span: token.span,
+            })
+        })
+    }
+
+    /// `(function input input1)`
+    fn parse_expr(token: Token) -> Result<Expr, Diagnostic> {
+        let inner = &token.text[1..token.text.len() - 1];
+        let mut token_v = tokenize(inner, token.span.line)?;
+        for inner_token in &mut token_v {
+            inner_token.span.column += token.span.column;
+        }
+        if token_v.len() != 3 {
+            return Err(Diagnostic {
+                span: token.span,
+                message: format!("expected 3 words in an expression, found {}", token_v.len()),
+            });
+        }
+
+        let mut token_v = token_v.into_iter();
+        Ok(Expr {
+            function: parse_arg(token_v.next().unwrap())?,
+            input: parse_arg(token_v.next().unwrap())?,
+            input1: parse_arg(token_v.next().unwrap())?,
+            span: token.span,
+        })
+    }
+
+    fn parse_output(token: Token) -> Result<Arg, Diagnostic> {
+        let arg = parse_arg(token)?;
+        if let Arg::Expr(_) = &arg {
+            return Err(Diagnostic {
+                span: arg.span(),
+                message: format!("an expression can not be an output"),
+            });
+        }
+        Ok(arg)
+    }
+
+    /// A line is a statement or a part of a block.
+    pub enum Line {
+        Stmt(Stmt),
+        /// `if condition {`
+        If(Arg, Span),
+        /// `} else {`
+        Else(Span),
+        /// `while condition {`
+        While(Arg, Span),
+        /// `for item in path {`
+        For(Arg, Arg, Span),
+        /// `}`
+        End(Span),
+    }
+
+    /// A block opened and not closed yet.
+    pub struct Block {
+        pub open: Line,
+        pub then_v: Option<Vec<Node>>,
+        pub node_v: Vec<Node>,
+    }
+
+    impl Block {
+        pub fn span(&self) -> Span {
+            match &self.open {
+                Line::If(_, span) | Line::While(_, span) | Line::For(_, _, span) => *span,
+                Line::Stmt(stmt) => stmt.span,
+                Line::Else(span) | Line::End(span) => *span,
+            }
+        }
+
+        pub fn close(self) -> Node {
+            match self.open {
+                Line::If(cond, span) => match self.then_v {
+                    Some(then_v) => Node::If {
+                        cond,
+                        then_v,
+                        else_v: self.node_v,
+                        span,
+                    },
+                    None => Node::If {
+                        cond,
+                        then_v: self.node_v,
+                        else_v: Vec::new(),
+                        span,
+                    },
+                },
+                Line::While(cond, span) => Node::While {
+                    cond,
+                    body_v: self.node_v,
+                    span,
+                },
+                Line::For(item, path, span) => Node::For {
+                    item,
+                    path,
+                    body_v: self.node_v,
+                    span,
+                },
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn line_span(token_v: &[Token], line_no: usize) -> Span {
+        Span {
+            line: line_no,
+            column: token_v[0].span.column,
+            len: token_v.last().unwrap().span.column + token_v.last().unwrap().span.len
+                - token_v[0].span.column,
+        }
+    }
+
+    pub fn parse_line(token_v: Vec<Token>, line_no: usize) -> Result<Line, Diagnostic> {
+        let span = line_span(&token_v, line_no);
+        let is_block = token_v.len() > 1 && token_v.last().unwrap().text == "{";
+        match token_v[0].text.as_str() {
+            "}" => {
+                if token_v.len() == 1 {
+                    return Ok(Line::End(span));
+                }
+                if token_v.len() == 3 && token_v[1].text == "else" && is_block {
+                    return Ok(Line::Else(span));
+                }
+                Err(Diagnostic {
+                    span: token_v[1].span,
+                    message: format!("expected `}}` or `}} else {{`"),
+                })
+            }
+            "if" | "while" if is_block => {
+                let keyword = token_v[0].text.clone();
+                if token_v.len() != 3 {
+                    return Err(Diagnostic {
+                        span,
+                        message: format!("expected `{keyword} condition {{`"),
+                    });
+                }
+                let cond = parse_arg(token_v.into_iter().nth(1).unwrap())?;
+                Ok(if keyword == "if" {
+                    Line::If(cond, span)
+                } else {
+                    Line::While(cond, span)
+                })
+            }
+            "for" if is_block => {
+                if token_v.len() != 5 || token_v[2].text != "in" {
+                    return Err(Diagnostic {
+                        span,
+                        message: format!("expected `for item in path {{`"),
+                    });
+                }
+                let mut token_v = token_v.into_iter().skip(1);
+                let item = parse_output(token_v.next().unwrap())?;
+                token_v.next();
+                let path = parse_arg(token_v.next().unwrap())?;
+                Ok(Line::For(item, path, span))
+            }
+            _ => parse_stmt(token_v, line_no).map(Line::Stmt),
+        }
+    }
+
+    fn parse_stmt(token_v: Vec<Token>, line_no: usize) -> Result<Stmt, Diagnostic> {
+        let span = line_span(&token_v, line_no);
+
+        if token_v.len() < 4 {
+            let last = token_v.last().unwrap();
+            return Err(Diagnostic {
+                span: Span {
+                    line: line_no,
+                    column: last.span.column + last.span.len,
+                    len: 1,
+                },
+                message: format!("expected 4 words in a statement, found {}", token_v.len()),
+            });
+        }
+
+        // the operator is followed by exactly 3 words, any word before it is an output
+        let op_pos = if token_v.len() >= 5 {
+            [token_v.len() - 4, 1]
+                .into_iter()
+                .find(|pos| parse_op(&token_v[*pos].text).is_some())
+        } else {
+            None
+        };
+        if token_v.len() == 5 && op_pos.is_none() {
+            return Err(Diagnostic {
+                span: token_v[1].span,
+                message: format!("unknown operator: {}", token_v[1].text),
+            });
+        }
+        let expected = match op_pos {
+            Some(op_pos) => op_pos + 4,
+            None => 4,
+        };
+        if token_v.len() > expected {
+            let token = &token_v[expected];
+            return Err(Diagnostic {
+                span: token.span,
+                message: format!("unexpected word: {}", token.text),
+            });
+        }
+
+        let op = match op_pos {
+            Some(op_pos) => parse_op(&token_v[op_pos].text).unwrap(),
+            None => Operator::Call,
+        };
+        if op == Operator::AddAssign && op_pos != Some(1) {
+            return Err(Diagnostic {
+                span: token_v[1].span,
+                message: format!("+= takes only one output"),
+            });
+        }
+
+        let mut token_v = token_v.into_iter();
+        let output = parse_output(token_v.next().unwrap())?;
+        let ret_v = match op_pos {
+            Some(op_pos) => {
+                let ret_v = token_v
+                    .by_ref()
+                    .take(op_pos - 1)
+                    .map(parse_output)
+                    .collect::<Result<Vec<Arg>, Diagnostic>>()?;
+                token_v.next();
+                ret_v
+            }
+            None => Vec::new(),
+        };
+        Ok(Stmt {
+            output,
+            ret_v,
+            op,
+            function: parse_arg(token_v.next().unwrap())?,
+            input: parse_arg(token_v.next().unwrap())?,
+            input1: parse_arg(token_v.next().unwrap())?,
+            span,
+        })
+    }
+
+    fn parse_op(word: &str) -> Option<Operator> {
+        match word {
+            "=" => Some(Operator::Assign),
+            "+=" => Some(Operator::AddAssign),
+            _ => None,
+        }
+    }
+
+    /// Whether the word is exactly one quoted string.
+    fn is_quoted(word: &str) -> bool {
+        if word.len() < 2 || !word.starts_with('\'') {
+            return false;
+        }
+        let mut escaped = false;
+        for (pos, ch) in word.char_indices().skip(1) {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '\'' {
+                return pos == word.len() - 1;
+            }
+        }
+        false
+    }
+}
+
+/// Position of a word in a script, `line` and `column` start from 1 and count chars.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+/// A syntax error found in a script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+/// `root->paper:code`, `_`, `?` or any other unquoted word.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathLit {
+    pub text: String,
+    pub span: Span,
+}
+
+/// `'quoted string'`, `value` is unescaped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StrLit {
+    pub value: String,
+    pub span: Span,
+}
+
+/// `(function input input1)`, evaluated before the statement using it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expr {
+    pub function: Arg,
+    pub input: Arg,
+    pub input1: Arg,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Arg {
+    Path(PathLit),
+    Str(StrLit),
+    Expr(Box<Expr>),
+}
+
+impl Arg {
+    pub fn span(&self) -> Span {
+        match self {
+            Arg::Path(lit) => lit.span,
+            Arg::Str(lit) => lit.span,
+            Arg::Expr(expr) => expr.span,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    /// `output function input input1`
+    Call,
+    /// `output = function input input1` or `output ret ret1 = function input input1`
+    Assign,
+    /// `output += function input input1`
+    AddAssign,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stmt {
+    pub output: Arg,
+    /// Outputs after the first one, bound to the named return values of the function.
+    pub ret_v: Vec<Arg>,
+    pub op: Operator,
+    pub function: Arg,
+    pub input: Arg,
+    pub input1: Arg,
+    pub span: Span,
+}
+
+/// A statement or a block of statements, a condition is true if it has any item.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    Stmt(Stmt),
+    If {
+        cond: Arg,
+        then_v: Vec<Node>,
+        else_v: Vec<Node>,
+        span: Span,
+    },
+    While {
+        cond: Arg,
+        body_v: Vec<Node>,
+        span: Span,
+    },
+    /// Run the body with every item of `path` in `item`.
+    For {
+        item: Arg,
+        path: Arg,
+        body_v: Vec<Node>,
+        span: Span,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Script {
+    pub node_v: Vec<Node>,
+}
+
+/// Parse all lines of `script`, every syntax error is reported.
+pub fn parse(script: &[String]) -> Result<Script, Vec<Diagnostic>> {
+    let mut node_v = Vec::new();
+    let mut block_v: Vec<main::Block> = Vec::new();
+    let mut diagnostic_v = Vec::new();
+
+    for (i, line) in script.iter().enumerate() {
+        let line_no = i + 1;
+        let token_v = match main::tokenize(line, line_no) {
+            Ok(token_v) => token_v,
+            Err(diagnostic) => {
+                diagnostic_v.push(diagnostic);
+                continue;
+            }
+        };
+        if token_v.is_empty() {
+            continue;
+        }
+        let line = match main::parse_line(token_v, line_no) {
+            Ok(line) => line,
+            Err(diagnostic) => {
+                diagnostic_v.push(diagnostic);
+                continue;
+            }
+        };
+        match line {
+            main::Line::Stmt(stmt) => match block_v.last_mut() {
+                Some(block) => block.node_v.push(Node::Stmt(stmt)),
+                None => node_v.push(Node::Stmt(stmt)),
+            },
+            main::Line::Else(span) => match block_v.last_mut() {
+                Some(block)
+                    if matches!(block.open, main::Line::If(..)) && block.then_v.is_none() =>
+                {
+                    block.then_v = Some(std::mem::take(&mut block.node_v));
+                }
+                _ => diagnostic_v.push(Diagnostic {
+                    span,
+                    message: format!("else without if"),
+                }),
+            },
+            main::Line::End(span) => match block_v.pop() {
+                Some(block) => {
+                    let node = block.close();
+                    match block_v.last_mut() {
+                        Some(block) => block.node_v.push(node),
+                        None => node_v.push(node),
+                    }
+                }
+                None => diagnostic_v.push(Diagnostic {
+                    span,
+                    message: format!("unexpected }}"),
+                }),
+            },
+            open => block_v.push(main::Block {
+                open,
+                then_v: None,
+                node_v: Vec::new(),
+            }),
+        }
+    }
+    for block in &block_v {
+        diagnostic_v.push(Diagnostic {
+            span: block.span(),
+            message: format!("unclosed block"),
+        });
+    }
+
+    if diagnostic_v.is_empty() {
+        Ok(Script { node_v })
+    } else {
+        Err(diagnostic_v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Arg, Node, Operator, Stmt};
+
+    fn stmt(node: &Node) -> &Stmt {
+        match node {
+            Node::Stmt(stmt) => stmt,
+            _ => panic!("not a statement"),
+        }
+    }
+
+    #[test]
+    fn should_parse_stmt() {
+        let script = parse(&vec![
+            "# comment".to_string(),
+            "".to_string(),
+            "$->$:output  =\tappend 'a b'  _ # tail".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(script.node_v.len(), 1);
+
+        let stmt = stmt(&script.node_v[0]);
+        assert_eq!(stmt.op, Operator::Assign);
+        assert_eq!(stmt.span.line, 3);
+        match &stmt.input {
+            Arg::Str(lit) => {
+                assert_eq!(lit.value, "a b");
+                assert_eq!(lit.span.column, 23);
+            }
+            _ => panic!("not a string"),
+        }
+    }
+
+    #[test]
+    fn should_parse_ret() {
+        let script = parse(&vec![
+            "$->$:output $->$:status = f _ _".to_string(),
+            "$->$:output = = _ _".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(stmt(&script.node_v[0]).ret_v.len(), 1);
+        assert_eq!(stmt(&script.node_v[0]).ret_v[0].span().column, 13);
+        assert!(stmt(&script.node_v[1]).ret_v.is_empty());
+
+        let diagnostic_v = parse(&vec!["$->$:a $->$:b += f _ _".to_string()]).unwrap_err();
+        assert_eq!(diagnostic_v[0].message, "+= takes only one output");
+    }
+
+    #[test]
+    fn should_parse_expr() {
+        let script = parse(&vec!["$->$:output = + 1 (* (- 3 1) '4 5')".to_string()]).unwrap();
+        let expr = match &stmt(&script.node_v[0]).input1 {
+            Arg::Expr(expr) => expr,
+            _ => panic!("not an expression"),
+        };
+        assert_eq!(expr.span.column, 19);
+        assert_eq!(expr.input.span().column, 22);
+        match &expr.input1 {
+            Arg::Str(lit) => assert_eq!(lit.value, "4 5"),
+            _ => panic!("not a string"),
+        }
+
+        let diagnostic_v = parse(&vec![
+            "$->$:output = + 1 (* 2".to_string(),
+            "$->$:output = + 1 (* 2)".to_string(),
+            "(+ 1 2) = + 1 2".to_string(),
+        ])
+        .unwrap_err();
+        assert_eq!(diagnostic_v[0].to_string(), "1:19: unclosed parenthesis");
+        assert_eq!(
+            diagnostic_v[1].message,
+            "expected 3 words in an expression, found 2"
+        );
+        assert_eq!(diagnostic_v[2].span.column, 1);
+    }
+
+    #[test]
+    fn should_parse_filter() {
+        let script = parse(&vec![
+            "$->$:output = root->app:user[app:name == 'bob b']->app:email _".to_string(),
+        ])
+        .unwrap();
+        match &stmt(&script.node_v[0]).input {
+            Arg::Path(lit) => {
+                assert_eq!(lit.text, "root->app:user[app:name == 'bob b']->app:email")
+            }
+            _ => panic!("not a path"),
+        }
+
+        let diagnostic_v =
+            parse(&vec!["$->$:output = root->app:user[app:name _".to_string()]).unwrap_err();
+        assert_eq!(diagnostic_v[0].to_string(), "1:29: unclosed bracket");
+    }
+
+    #[test]
+    fn should_check_path() {
+        let diagnostic_v = parse(&vec![
+            "$->$:output = root->app:user->app: _".to_string(),
+            "$->$:output = + 1 (* root->->a 2)".to_string(),
+            "$->$:output->$:a:b = _ _".to_string(),
+        ])
+        .unwrap_err();
+        assert_eq!(diagnostic_v[0].to_string(), "1:35: expected code");
+        assert_eq!(
+            diagnostic_v[1].to_string(),
+            "2:26: expected paper:code after ->"
+        );
+        assert_eq!(diagnostic_v[2].to_string(), "3:17: unexpected :");
+    }
+
+    #[test]
+    fn should_parse_block() {
+        let script = parse(&vec![
+            "for $->$:item in $->$:item_v {".to_string(),
+            "    if (== $->$:item 1) {".to_string(),
+            "        $->$:output += = $->$:item _".to_string(),
+            "    } else {".to_string(),
+            "    }".to_string(),
+            "}".to_string(),
+        ])
+        .unwrap();
+        match &script.node_v[0] {
+            Node::For { body_v, .. } => match &body_v[0] {
+                Node::If { then_v, else_v, .. } => {
+                    assert_eq!(then_v.len(), 1);
+                    assert!(else_v.is_empty());
+                }
+                _ => panic!("not an if"),
+            },
+            _ => panic!("not a for"),
+        }
+
+        let diagnostic_v = parse(&vec![
+            "while $->$:a {".to_string(),
+            "} else {".to_string(),
+            "}".to_string(),
+            "}".to_string(),
+            "if $->$:a {".to_string(),
+        ])
+        .unwrap_err();
+        let message_v: Vec<&str> = diagnostic_v.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            message_v,
+            ["else without if", "unexpected }", "unclosed block"]
+        );
+        assert_eq!(diagnostic_v[2].span.line, 5);
+    }
+
+    #[test]
+    fn should_report_every_error() {
+        let diagnostic_v = parse(&vec![
+            "$->$:output append".to_string(),
+            "$->$:output append $->$:input _".to_string(),
+            "$->$:output -= append $->$:input _".to_string(),
+            "$->$:output append 'abc _".to_string(),
+        ])
+        .unwrap_err();
+        assert_eq!(diagnostic_v.len(), 3);
+        assert_eq!(
+            diagnostic_v[0].to_string(),
+            "1:19: expected 4 words in a statement, found 2"
+        );
+        assert_eq!(diagnostic_v[1].span.line, 3);
+        assert_eq!(diagnostic_v[1].span.column, 13);
+        assert_eq!(diagnostic_v[2].span.column, 20);
+    }
+}
@@ -0,0 +1,217 @@
+//! Native functions registered by the application on an [EdgeEngine](super::EdgeEngine).
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    pin::Pin,
+    sync::Arc,
+};
+
+use crate::{
+    err,
+    util::{
+        data::{AsDataManager, Fu},
+        func, Path,
+    },
+};
+
+/// A function callable from scripts.
+///
+/// It gets the same arguments as a builtin: the engine as `dm`, the output path and the inputs
+/// converted by `temp_2_global`.
+pub trait AsFunction: Send + Sync {
+    fn call<'a, 'a1, 'a2, 'a3, 'a4, 'f>(
+        &'a self,
+        dm: &'a1 mut dyn AsDataManager,
+        output: &'a2 Path,
+        input: &'a3 Path,
+        input1: &'a4 Path,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+        'a2: 'f,
+        'a3: 'f,
+        'a4: 'f;
+}
+
+/// Wrap an async closure from the items of `input` and `input1` to the items of `output`.
+pub struct FnFunction<F> {
+    f: F,
+}
+
+impl<F, R> AsFunction for FnFunction<F>
+where
+    F: Fn(Vec<String>, Vec<String>) -> R + Send + Sync,
+    R: Fu<Output = err::Result<Vec<String>>> + 'static,
+{
+    fn call<'a, 'a1, 'a2, 'a3, 'a4, 'f>(
+        &'a self,
+        dm: &'a1 mut dyn AsDataManager,
+        output: &'a2 Path,
+        input: &'a3 Path,
+        input1: &'a4 Path,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+        'a2: 'f,
+        'a3: 'f,
+        'a4: 'f,
+    {
+        Box::pin(async move {
+            let input_item_v = dm.get(input).await?;
+            let input1_item_v = dm.get(input1).await?;
+            let rs = (self.f)(input_item_v, input1_item_v).await?;
+            dm.set(output, rs).await
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionInfo {
+    pub name: String,
+    /// Names of the used arguments, in the order of `input`, `input1`.
+    pub arg_v: Vec<String>,
+    pub is_builtin: bool,
+}
+
+impl FunctionInfo {
+    pub fn arity(&self) -> usize {
+        self.arg_v.len()
+    }
+}
+
+pub(crate) enum Lookup {
+    Native(Arc<dyn AsFunction>),
+    Disabled,
+    Builtin,
+}
+
+/// Functions of an engine besides the builtins.
+///
+/// A registered function overrides the builtin with the same name, calling a disabled name is
+/// denied.
+#[derive(Clone, Default)]
+pub struct FunctionRegistry {
+    function_mp: BTreeMap<String, (FunctionInfo, Arc<dyn AsFunction>)>,
+    disabled_set: HashSet<String>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<F>(&mut self, name: &str, arg_v: &[&str], function: F)
+    where
+        F: AsFunction + 'static,
+    {
+        self.disabled_set.remove(name);
+        self.function_mp.insert(
+            name.to_string(),
+            (
+                FunctionInfo {
+                    name: name.to_string(),
+                    arg_v: arg_v.iter().map(|arg| arg.to_string()).collect(),
+                    is_builtin: false,
+                },
+                Arc::new(function),
+            ),
+        );
+    }
+
+    /// # Register an async closure.
+    ///
+    /// ```
+    /// use edge_lib::util::engine::registry::FunctionRegistry;
+    ///
+    /// let mut registry = FunctionRegistry::new();
+    /// registry.register_fn("concat", &["left", "right"], |mut left, right| async move {
+    ///     left.extend(right);
+    ///     Ok(left)
+    /// });
+    /// ```
+    pub fn register_fn<F, R>(&mut self, name: &str, arg_v: &[&str], f: F)
+    where
+        F: Fn(Vec<String>, Vec<String>) -> R + Send + Sync + 'static,
+        R: Fu<Output = err::Result<Vec<String>>> + 'static,
+    {
+        self.register(name, arg_v, FnFunction { f })
+    }
+
+    /// Remove the registered function, the builtin with the same name is available again.
+    pub fn unregister(&mut self, name: &str) {
+        self.function_mp.remove(name);
+        self.disabled_set.remove(name);
+    }
+
+    /// Make the function unavailable, no matter it is registered or builtin.
+    pub fn disable(&mut self, name: &str) {
+        self.function_mp.remove(name);
+        self.disabled_set.insert(name.to_string());
+    }
+
+    pub fn is_available(&self, name: &str) -> bool {
+        match self.lookup(name) {
+            Lookup::Native(_) => true,
+            Lookup::Disabled => false,
+            Lookup::Builtin => func::BUILTIN_V.iter().any(|(builtin, _)| *builtin == name),
+        }
+    }
+
+    /// All available functions, builtins first.
+    pub fn info_v(&self) -> Vec<FunctionInfo> {
+        let mut info_v: Vec<FunctionInfo> = func::BUILTIN_V
+            .iter()
+            .filter(|(name, _)| {
+                !self.disabled_set.contains(*name) && !self.function_mp.contains_key(*name)
+            })
+            .map(|(name, arg_v)| FunctionInfo {
+                name: name.to_string(),
+                arg_v: arg_v.iter().map(|arg| arg.to_string()).collect(),
+                is_builtin: true,
+            })
+            .collect();
+        info_v.extend(self.function_mp.values().map(|(info, _)| info.clone()));
+        info_v
+    }
+
+    pub(crate) fn lookup(&self, name: &str) -> Lookup {
+        if let Some((_, function)) = self.function_mp.get(name) {
+            Lookup::Native(function.clone())
+        } else if self.disabled_set.contains(name) {
+            Lookup::Disabled
+        } else {
+            Lookup::Builtin
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FunctionRegistry;
+
+    #[test]
+    fn should_override_and_disable() {
+        let mut registry = FunctionRegistry::new();
+        registry.register_fn("+", &["left", "right"], |left, _| async move { Ok(left) });
+        registry.disable("rand");
+
+        assert!(registry.is_available("+"));
+        assert!(!registry.is_available("rand"));
+        assert!(!registry.is_available("no_such_function"));
+
+        let info_v = registry.info_v();
+        assert!(info_v.iter().all(|info| info.name != "rand"));
+        let add = info_v.iter().find(|info| info.name == "+").unwrap();
+        assert!(!add.is_builtin);
+        assert_eq!(add.arity(), 2);
+
+        registry.unregister("+");
+        registry.unregister("rand");
+        let info_v = registry.info_v();
+        assert!(info_v
+            .iter()
+            .any(|info| info.name == "rand" && info.is_builtin));
+    }
+}
@@ -0,0 +1,146 @@
+//! Observing the instructions executed by an [EdgeEngine](super::EdgeEngine).
+
+use std::{fmt::Display, future, pin::Pin, sync::Mutex, time::Duration};
+
+use crate::{
+    err,
+    util::{data::Fu, Path},
+};
+
+use super::Inc;
+
+/// An instruction about to run.
+#[derive(Clone, Debug)]
+pub struct IncEvent {
+    /// Depth of the script, 0 for the top-level one.
+    pub depth: usize,
+    /// Number of the instructions executed before it in its script, not counting the ones of
+    /// called scripts.
+    pub index: usize,
+    pub inc: Inc,
+    /// Name of the called function.
+    pub function: String,
+    /// `inc.input` converted by `temp_2_global`.
+    pub input: Path,
+    pub input1: Path,
+    pub input_item_v: Vec<String>,
+    pub input1_item_v: Vec<String>,
+}
+
+/// What an instruction did.
+#[derive(Clone, Debug)]
+pub struct IncResult {
+    /// Items of `inc.output` after the instruction, empty if failed.
+    pub output_item_v: Vec<String>,
+    pub duration: Duration,
+    pub error: Option<String>,
+}
+
+/// # Hooks around every instruction.
+///
+/// Both hooks are awaited by the engine, so a debugger can pause the script in
+/// [AsObserver::before] and stop it by returning an error.
+pub trait AsObserver: Send + Sync {
+    #[allow(unused)]
+    fn before<'a, 'a1, 'f>(
+        &'a self,
+        event: &'a1 IncEvent,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+    {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    #[allow(unused)]
+    fn after<'a, 'a1, 'a2, 'f>(
+        &'a self,
+        event: &'a1 IncEvent,
+        rs: &'a2 IncResult,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+        'a2: 'f,
+    {
+        Box::pin(future::ready(Ok(())))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub event: IncEvent,
+    pub rs: IncResult,
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let event = &self.event;
+        write!(
+            f,
+            "{}{}: {} {} {}{:?} {}{:?}",
+            "  ".repeat(event.depth),
+            event.index,
+            event.inc.output,
+            event.function,
+            event.input,
+            event.input_item_v,
+            event.input1,
+            event.input1_item_v,
+        )?;
+        match &self.rs.error {
+            Some(error) => write!(f, " => error: {error}")?,
+            None => write!(f, " => {:?}", self.rs.output_item_v)?,
+        }
+        write!(f, " ({:?})", self.rs.duration)
+    }
+}
+
+/// Record every finished instruction, displayed as a trace with one line per instruction.
+#[derive(Default)]
+pub struct Recorder {
+    record_v: Mutex<Vec<Record>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_v(&self) -> Vec<Record> {
+        self.record_v.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.record_v.lock().unwrap().clear();
+    }
+}
+
+impl AsObserver for Recorder {
+    fn after<'a, 'a1, 'a2, 'f>(
+        &'a self,
+        event: &'a1 IncEvent,
+        rs: &'a2 IncResult,
+    ) -> Pin<Box<dyn Fu<Output = err::Result<()>> + 'f>>
+    where
+        'a: 'f,
+        'a1: 'f,
+        'a2: 'f,
+    {
+        self.record_v.lock().unwrap().push(Record {
+            event: event.clone(),
+            rs: rs.clone(),
+        });
+        Box::pin(future::ready(Ok(())))
+    }
+}
+
+impl Display for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for record in self.record_v.lock().unwrap().iter() {
+            writeln!(f, "{record}")?;
+        }
+        Ok(())
+    }
+}
@@ -6,6 +6,42 @@ use crate::{err, util::Path};
 
 use super::data::{AsDataManager, Fu};
 
+/// Builtin functions with the names of their used arguments.
+pub const BUILTIN_V: &[(&str, &[&str])] = &[
+    ("new", &["size", "item"]),
+    ("line", &["size"]),
+    ("rand", &["size"]),
+    ("+=", &["input", "input1"]),
+    ("append", &["input", "input1"]),
+    ("distinct", &["input"]),
+    ("left", &["input", "input1"]),
+    ("inner", &["input", "input1"]),
+    ("if", &["input", "input1"]),
+    ("if0", &["input", "input1"]),
+    ("if1", &["input", "input1"]),
+    ("+", &["left", "right"]),
+    ("-", &["left", "right"]),
+    ("*", &["left", "right"]),
+    ("/", &["left", "right"]),
+    ("%", &["left", "right"]),
+    ("==", &["left", "right"]),
+    ("!=", &["left", "right"]),
+    (">", &["left", "right"]),
+    ("<", &["left", "right"]),
+    ("count", &["input"]),
+    ("sum", &["input"]),
+    ("=", &["input"]),
+    ("remove", &["input"]),
+    ("clear", &[]),
+    ("slice", &["input", "range"]),
+    ("sort", &["input", "order"]),
+    ("sort_s", &["input", "order"]),
+    ("dump", &["root", "paper"]),
+    ("while0", &["path", "timeout"]),
+    ("while1", &["path", "timeout"]),
+    ("get_code_v", &["root", "paper"]),
+];
+
 mod inner {
     use std::collections::HashSet;
 
@@ -137,6 +173,25 @@ where
     dm.set(output, input_item_v).await
 }
 
+/// Remove the items of `input` from `output`.
+#[allow(unused)]
+pub async fn remove<DM>(dm: &mut DM, output: &Path, input: &Path, input1: &Path) -> err::Result<()>
+where
+    DM: AsDataManager + ?Sized,
+{
+    let input_item_v = dm.get(input).await?;
+    dm.delete(output, input_item_v).await
+}
+
+/// Remove every item of `output`, or every edge of its roots without steps.
+#[allow(unused)]
+pub async fn clear<DM>(dm: &mut DM, output: &Path, input: &Path, input1: &Path) -> err::Result<()>
+where
+    DM: AsDataManager + ?Sized,
+{
+    dm.delete_all(output).await
+}
+
 pub async fn add<DM>(dm: &mut DM, output: &Path, input: &Path, input1: &Path) -> err::Result<()>
 where
     DM: AsDataManager + ?Sized,
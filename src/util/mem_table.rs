@@ -1,4 +1,10 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::{collections::BTreeSet, io, sync::Arc};
+
+use im::{HashMap, OrdMap, OrdSet, Vector};
+
+/// First line of a snapshot, followed by its version and its checkpoint generation.
+const SNAPSHOT_HEADER: &str = "edge-mem-table";
+const SNAPSHOT_VERSION: u32 = 2;
 
 fn next_id(id: &mut u64) -> u64 {
     let new_id = *id;
@@ -6,6 +12,137 @@ fn next_id(id: &mut u64) -> u64 {
     new_id
 }
 
+/// Keep a field of a snapshot in one column of one line.
+fn escape_field(field: &str) -> String {
+    let mut rs = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => rs.push_str("\\\\"),
+            '\t' => rs.push_str("\\t"),
+            '\n' => rs.push_str("\\n"),
+            '\r' => rs.push_str("\\r"),
+            _ => rs.push(c),
+        }
+    }
+    rs
+}
+
+fn unescape_field(field: &str) -> io::Result<String> {
+    let mut rs = String::with_capacity(field.len());
+    let mut char_iter = field.chars();
+    while let Some(c) = char_iter.next() {
+        if c != '\\' {
+            rs.push(c);
+            continue;
+        }
+        match char_iter.next() {
+            Some('\\') => rs.push('\\'),
+            Some('t') => rs.push('\t'),
+            Some('n') => rs.push('\n'),
+            Some('r') => rs.push('\r'),
+            _ => return Err(invalid_data(format!("invalid escape in {field}"))),
+        }
+    }
+    Ok(rs)
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Id of a string interned by a table.
+type Sym = u32;
+
+/// Strings of a table as compact ids, each counting the edges it is in.
+///
+/// A string no edge is in any more is dropped, and its id is taken by the next new string.
+#[derive(Clone, Default)]
+struct Interner {
+    /// `None` for a free id.
+    entry_v: Vector<Option<(Arc<str>, usize)>>,
+    sym_mp: HashMap<Arc<str>, Sym>,
+    free_v: Vector<Sym>,
+}
+
+impl Interner {
+    fn get(&self, s: &str) -> Option<Sym> {
+        self.sym_mp.get(s).copied()
+    }
+
+    /// Id of `s` counted once more, `None` if every id is taken.
+    fn intern(&mut self, s: &str) -> Option<Sym> {
+        if let Some(sym) = self.get(s) {
+            if let Some((_, cnt)) = &mut self.entry_v[sym as usize] {
+                *cnt += 1;
+            }
+            return Some(sym);
+        }
+        let s: Arc<str> = Arc::from(s);
+        let sym = match self.free_v.pop_back() {
+            Some(sym) => {
+                self.entry_v.set(sym as usize, Some((s.clone(), 1)));
+                sym
+            }
+            None => {
+                let sym = Sym::try_from(self.entry_v.len()).ok()?;
+                self.entry_v.push_back(Some((s.clone(), 1)));
+                sym
+            }
+        };
+        self.sym_mp.insert(s, sym);
+        Some(sym)
+    }
+
+    /// Count `sym` once less, dropping its string at zero.
+    fn release(&mut self, sym: Sym) {
+        let entry = &mut self.entry_v[sym as usize];
+        if let Some((s, cnt)) = entry {
+            *cnt -= 1;
+            if *cnt == 0 {
+                self.sym_mp.remove(s);
+                *entry = None;
+                self.free_v.push_back(sym);
+            }
+        }
+    }
+
+    fn resolve(&self, sym: Sym) -> String {
+        match &self.entry_v[sym as usize] {
+            Some((s, _)) => s.to_string(),
+            None => String::new(),
+        }
+    }
+}
+
+/// [Edge] by the interned strings.
+#[derive(Clone, Copy)]
+struct SymEdge {
+    source: Sym,
+    paper: Sym,
+    code: Sym,
+    target: Sym,
+}
+
+fn insert_uuid<K: Ord + Clone>(inx: &mut OrdMap<K, OrdSet<u64>>, k: K, uuid: u64) {
+    match inx.get_mut(&k) {
+        Some(set) => {
+            set.insert(uuid);
+        }
+        None => {
+            inx.insert(k, OrdSet::unit(uuid));
+        }
+    }
+}
+
+fn remove_uuid<K: Ord + Clone>(inx: &mut OrdMap<K, OrdSet<u64>>, k: &K, uuid: u64) {
+    if let Some(set) = inx.get_mut(k) {
+        set.remove(&uuid);
+        if set.is_empty() {
+            inx.remove(k);
+        }
+    }
+}
+
 // Public
 #[derive(Clone)]
 pub struct Edge {
@@ -15,158 +152,590 @@ pub struct Edge {
     pub target: String,
 }
 
+/// A change of [MemTable], what the log of a data manager records.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TableOp {
+    InsertEdge(String, String, String, String),
+    DeleteEdge(String, String, String, String),
+    DeleteEdgeWithSourceCode(String, String, String),
+    DeleteEdgeWithCodeTarget(String, String, String),
+    DeleteEdgeWithNode(String),
+    ClearPaper(String),
+    Clear,
+}
+
+impl TableOp {
+    /// One line, the name and the arguments split by tabs.
+    pub fn to_record(&self) -> String {
+        let (name, arg_v): (&str, Vec<&String>) = match self {
+            TableOp::InsertEdge(source, paper, code, target) => {
+                ("insert_edge", vec![source, paper, code, target])
+            }
+            TableOp::DeleteEdge(source, paper, code, target) => {
+                ("delete_edge", vec![source, paper, code, target])
+            }
+            TableOp::DeleteEdgeWithSourceCode(source, paper, code) => {
+                ("delete_edge_with_source_code", vec![source, paper, code])
+            }
+            TableOp::DeleteEdgeWithCodeTarget(paper, code, target) => {
+                ("delete_edge_with_code_target", vec![paper, code, target])
+            }
+            TableOp::DeleteEdgeWithNode(node) => ("delete_edge_with_node", vec![node]),
+            TableOp::ClearPaper(paper) => ("clear_paper", vec![paper]),
+            TableOp::Clear => ("clear", vec![]),
+        };
+        let mut record = name.to_string();
+        for arg in arg_v {
+            record.push('\t');
+            record.push_str(&escape_field(arg));
+        }
+        record
+    }
+
+    pub fn from_record(record: &str) -> io::Result<Self> {
+        let mut field_iter = record.split('\t');
+        let name = field_iter.next().unwrap_or_default();
+        let arg_v = field_iter
+            .map(unescape_field)
+            .collect::<io::Result<Vec<String>>>()?;
+        let op = match (name, arg_v.as_slice()) {
+            ("insert_edge", [source, paper, code, target]) => {
+                TableOp::InsertEdge(source.clone(), paper.clone(), code.clone(), target.clone())
+            }
+            ("delete_edge", [source, paper, code, target]) => {
+                TableOp::DeleteEdge(source.clone(), paper.clone(), code.clone(), target.clone())
+            }
+            ("delete_edge_with_source_code", [source, paper, code]) => {
+                TableOp::DeleteEdgeWithSourceCode(source.clone(), paper.clone(), code.clone())
+            }
+            ("delete_edge_with_code_target", [paper, code, target]) => {
+                TableOp::DeleteEdgeWithCodeTarget(paper.clone(), code.clone(), target.clone())
+            }
+            ("delete_edge_with_node", [node]) => TableOp::DeleteEdgeWithNode(node.clone()),
+            ("clear_paper", [paper]) => TableOp::ClearPaper(paper.clone()),
+            ("clear", []) => TableOp::Clear,
+            _ => return Err(invalid_data(format!("invalid record: {record}"))),
+        };
+        Ok(op)
+    }
+}
+
+/// # Edges indexed by the ids of their interned strings.
+///
+/// Maps are shared by clones until changed, so a clone is a cheap snapshot.
 #[derive(Clone)]
 pub struct MemTable {
     id: u64,
-    edge_mp: BTreeMap<u64, Edge>,
-    inx_source_code: BTreeMap<(String, (String, String)), BTreeSet<u64>>,
-    inx_code_target: BTreeMap<((String, String), String), BTreeSet<u64>>,
-    inx_paper: BTreeMap<String, BTreeSet<u64>>,
+    interner: Interner,
+    edge_mp: OrdMap<u64, SymEdge>,
+    inx_source_code: OrdMap<(Sym, (Sym, Sym)), OrdSet<u64>>,
+    inx_code_target: OrdMap<((Sym, Sym), Sym), OrdSet<u64>>,
+    inx_target_paper: OrdMap<(Sym, Sym), OrdSet<u64>>,
+    inx_paper: OrdMap<Sym, OrdSet<u64>>,
 }
 
 impl MemTable {
     pub fn new() -> Self {
         Self {
             id: 0,
-            edge_mp: BTreeMap::new(),
-            inx_source_code: BTreeMap::new(),
-            inx_code_target: BTreeMap::new(),
-            inx_paper: BTreeMap::new(),
+            interner: Interner::default(),
+            edge_mp: OrdMap::new(),
+            inx_source_code: OrdMap::new(),
+            inx_code_target: OrdMap::new(),
+            inx_target_paper: OrdMap::new(),
+            inx_paper: OrdMap::new(),
         }
     }
 
-    pub fn insert_edge(&mut self, source: &str, paper: &str, code: &str, target: &str) -> u64 {
-        let uuid = next_id(&mut self.id);
-        let edge = Edge {
-            source: source.to_string(),
-            paper: paper.to_string(),
-            code: code.to_string(),
-            target: target.to_string(),
+    /// Id of the inserted edge, an `OutOfMemory` error if its strings can not be interned.
+    pub fn insert_edge(
+        &mut self,
+        source: &str,
+        paper: &str,
+        code: &str,
+        target: &str,
+    ) -> io::Result<u64> {
+        let uuid = self.id;
+        self.insert_edge_with_id(uuid, source, paper, code, target)?;
+        next_id(&mut self.id);
+        Ok(uuid)
+    }
+
+    fn insert_edge_with_id(
+        &mut self,
+        uuid: u64,
+        source: &str,
+        paper: &str,
+        code: &str,
+        target: &str,
+    ) -> io::Result<()> {
+        let mut sym_v = [0; 4];
+        for (i, s) in [source, paper, code, target].into_iter().enumerate() {
+            match self.interner.intern(s) {
+                Some(sym) => sym_v[i] = sym,
+                None => {
+                    for sym in &sym_v[0..i] {
+                        self.interner.release(*sym);
+                    }
+                    return Err(io::Error::new(
+                        io::ErrorKind::OutOfMemory,
+                        format!("too many strings"),
+                    ));
+                }
+            }
+        }
+        let [source, paper, code, target] = sym_v;
+        let edge = SymEdge {
+            source,
+            paper,
+            code,
+            target,
         };
-        let source_code_k = (edge.source.clone(), (edge.paper.clone(), edge.code.clone()));
-        match self.inx_source_code.get_mut(&source_code_k) {
-            Some(set) => {
-                set.insert(uuid);
+        insert_uuid(
+            &mut self.inx_source_code,
+            (edge.source, (edge.paper, edge.code)),
+            uuid,
+        );
+        insert_uuid(
+            &mut self.inx_code_target,
+            ((edge.paper, edge.code), edge.target),
+            uuid,
+        );
+        insert_uuid(&mut self.inx_target_paper, (edge.target, edge.paper), uuid);
+        insert_uuid(&mut self.inx_paper, edge.paper, uuid);
+        self.edge_mp.insert(uuid, edge);
+        Ok(())
+    }
+
+    fn to_edge(&self, edge: &SymEdge) -> Edge {
+        Edge {
+            source: self.interner.resolve(edge.source),
+            paper: self.interner.resolve(edge.paper),
+            code: self.interner.resolve(edge.code),
+            target: self.interner.resolve(edge.target),
+        }
+    }
+
+    /// Id of the next inserted edge.
+    pub fn get_next_id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn apply(&mut self, op: &TableOp) -> io::Result<()> {
+        match op {
+            TableOp::InsertEdge(source, paper, code, target) => {
+                self.insert_edge(source, paper, code, target)?;
             }
-            None => {
-                let mut set = BTreeSet::new();
-                set.insert(uuid);
-                self.inx_source_code.insert(source_code_k.clone(), set);
+            TableOp::DeleteEdge(source, paper, code, target) => {
+                self.delete_edge(source, paper, code, target)
             }
-        }
-        let code_target_k = ((edge.paper.clone(), edge.code.clone()), edge.target.clone());
-        match self.inx_code_target.get_mut(&code_target_k) {
-            Some(set) => {
-                set.insert(uuid);
+            TableOp::DeleteEdgeWithSourceCode(source, paper, code) => {
+                self.delete_edge_with_source_code(source, paper, code)
             }
-            None => {
-                let mut set = BTreeSet::new();
-                set.insert(uuid);
-                self.inx_code_target.insert(code_target_k.clone(), set);
+            TableOp::DeleteEdgeWithCodeTarget(paper, code, target) => {
+                self.delete_edge_with_code_target(paper, code, target)
             }
+            TableOp::DeleteEdgeWithNode(node) => self.delete_edge_with_node(node),
+            TableOp::ClearPaper(paper) => self.clear_paper(paper),
+            TableOp::Clear => self.clear(),
         }
-        match self.inx_paper.get_mut(&edge.paper) {
-            Some(set) => {
-                set.insert(uuid);
+        Ok(())
+    }
+
+    /// Inserts building the same edges in the same order from an empty table.
+    pub fn to_op_v(&self) -> Vec<TableOp> {
+        self.edge_mp
+            .values()
+            .map(|edge| {
+                let edge = self.to_edge(edge);
+                TableOp::InsertEdge(edge.source, edge.paper, edge.code, edge.target)
+            })
+            .collect()
+    }
+
+    /// # Write every edge and the id counter as lines.
+    ///
+    /// The first line is the format, its version and `generation`, the second the next id, then
+    /// one edge a line as `id source paper code target` split by tabs.
+    pub fn save_to<W: io::Write>(&self, mut w: W, generation: u64) -> io::Result<()> {
+        writeln!(w, "{SNAPSHOT_HEADER} {SNAPSHOT_VERSION} {generation}")?;
+        writeln!(w, "{}", self.id)?;
+        for (uuid, edge) in &self.edge_mp {
+            let edge = self.to_edge(edge);
+            writeln!(
+                w,
+                "{uuid}\t{}\t{}\t{}\t{}",
+                escape_field(&edge.source),
+                escape_field(&edge.paper),
+                escape_field(&edge.code),
+                escape_field(&edge.target)
+            )?;
+        }
+        w.flush()
+    }
+
+    /// # Table written by [MemTable::save_to] and its generation.
+    ///
+    /// The ids are kept so the order is kept, an id taken or not below the next id is
+    /// `InvalidData`. A snapshot of version 1 has no generation, its next id stands for it as the
+    /// base of its log did.
+    pub fn load_from<R: io::BufRead>(r: R) -> io::Result<(Self, u64)> {
+        let mut line_iter = r.lines();
+        let header = line_iter.next().transpose()?.unwrap_or_default();
+        let generation = match header.split(' ').collect::<Vec<&str>>().as_slice() {
+            [SNAPSHOT_HEADER, "1"] => None,
+            [SNAPSHOT_HEADER, version, generation] if *version == SNAPSHOT_VERSION.to_string() => {
+                let generation = generation
+                    .parse::<u64>()
+                    .map_err(|_| invalid_data(format!("invalid generation: {header}")))?;
+                Some(generation)
             }
-            None => {
-                let mut set = BTreeSet::new();
-                set.insert(uuid);
-                self.inx_paper.insert(edge.paper.clone(), set);
+            _ => return Err(invalid_data(format!("unsupported snapshot: {header}"))),
+        };
+        let id = line_iter
+            .next()
+            .transpose()?
+            .and_then(|line| line.parse::<u64>().ok())
+            .ok_or_else(|| invalid_data(format!("expected the next id")))?;
+        let generation = generation.unwrap_or(id);
+
+        let mut table = Self::new();
+        table.id = id;
+        for line in line_iter {
+            let line = line?;
+            let field_v: Vec<&str> = line.split('\t').collect();
+            if field_v.len() != 5 {
+                return Err(invalid_data(format!("invalid edge: {line}")));
             }
+            let uuid = match field_v[0].parse::<u64>() {
+                Ok(uuid) if uuid < id && !table.edge_mp.contains_key(&uuid) => uuid,
+                _ => return Err(invalid_data(format!("invalid id: {line}"))),
+            };
+            table.insert_edge_with_id(
+                uuid,
+                &unescape_field(field_v[1])?,
+                &unescape_field(field_v[2])?,
+                &unescape_field(field_v[3])?,
+                &unescape_field(field_v[4])?,
+            )?;
         }
-        self.edge_mp.insert(uuid, edge);
-        uuid
+        Ok((table, generation))
     }
 
     pub fn get_target_v(&self, source: &str, paper: &str, code: &str) -> Vec<String> {
-        if let Some(uuid_v) = self
+        let k = match (
+            self.interner.get(source),
+            self.interner.get(paper),
+            self.interner.get(code),
+        ) {
+            (Some(source), Some(paper), Some(code)) => (source, (paper, code)),
+            _ => return Vec::new(),
+        };
+        match self.inx_source_code.get(&k) {
+            Some(uuid_v) => uuid_v
+                .iter()
+                .map(|uuid| self.interner.resolve(self.edge_mp[uuid].target))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn get_source_v(&self, paper: &str, code: &str, target: &str) -> Vec<String> {
+        let k = match (
+            self.interner.get(paper),
+            self.interner.get(code),
+            self.interner.get(target),
+        ) {
+            (Some(paper), Some(code), Some(target)) => ((paper, code), target),
+            _ => return Vec::new(),
+        };
+        match self.inx_code_target.get(&k) {
+            Some(uuid_v) => uuid_v
+                .iter()
+                .map(|uuid| self.interner.resolve(self.edge_mp[uuid].source))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Targets of every code in `paper`, in inserted order.
+    pub fn get_target_v_of_paper(&self, source: &str, paper: &str) -> Vec<String> {
+        let (source, paper) = match (self.interner.get(source), self.interner.get(paper)) {
+            (Some(source), Some(paper)) => (source, paper),
+            _ => return Vec::new(),
+        };
+        let uuid_set: BTreeSet<u64> = self
             .inx_source_code
-            .get(&(source.to_string(), (paper.to_string(), code.to_string())))
-        {
-            let mut arr = Vec::with_capacity(uuid_v.len());
+            .range((source, (paper, Sym::MIN))..=(source, (paper, Sym::MAX)))
+            .flat_map(|(_, uuid_v)| uuid_v.iter().cloned())
+            .collect();
+        uuid_set
+            .iter()
+            .map(|uuid| self.interner.resolve(self.edge_mp[uuid].target))
+            .collect()
+    }
+
+    /// Sources of every code in `paper`, in inserted order.
+    pub fn get_source_v_of_paper(&self, paper: &str, target: &str) -> Vec<String> {
+        let k = match (self.interner.get(target), self.interner.get(paper)) {
+            (Some(target), Some(paper)) => (target, paper),
+            _ => return Vec::new(),
+        };
+        match self.inx_target_paper.get(&k) {
+            Some(uuid_v) => uuid_v
+                .iter()
+                .map(|uuid| self.interner.resolve(self.edge_mp[uuid].source))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn delete_edge_with_source_code(&mut self, source: &str, paper: &str, code: &str) {
+        let k = match (
+            self.interner.get(source),
+            self.interner.get(paper),
+            self.interner.get(code),
+        ) {
+            (Some(source), Some(paper), Some(code)) => (source, (paper, code)),
+            _ => return,
+        };
+        if let Some(uuid_v) = self.inx_source_code.get(&k).cloned() {
             for uuid in uuid_v {
-                let edge = &self.edge_mp[uuid];
-                arr.push(edge.target.clone());
+                self.remove_edge(uuid);
             }
-            arr
-        } else {
-            Vec::new()
         }
     }
 
-    pub fn get_source_v(&self, paper: &str, code: &str, target: &str) -> Vec<String> {
-        if let Some(uuid_v) = self
-            .inx_code_target
-            .get(&((paper.to_string(), code.to_string()), target.to_string()))
-        {
-            let mut arr = Vec::with_capacity(uuid_v.len());
+    pub fn delete_edge_with_code_target(&mut self, paper: &str, code: &str, target: &str) {
+        let k = match (
+            self.interner.get(paper),
+            self.interner.get(code),
+            self.interner.get(target),
+        ) {
+            (Some(paper), Some(code), Some(target)) => ((paper, code), target),
+            _ => return,
+        };
+        if let Some(uuid_v) = self.inx_code_target.get(&k).cloned() {
             for uuid in uuid_v {
-                let edge = &self.edge_mp[uuid];
-                arr.push(edge.source.clone());
+                self.remove_edge(uuid);
             }
-            arr
-        } else {
-            Vec::new()
         }
     }
 
-    pub fn delete_edge_with_source_code(&mut self, source: &str, paper: &str, code: &str) {
-        if let Some(uuid_v) = self
-            .inx_source_code
-            .remove(&(source.to_string(), (paper.to_string(), code.to_string())))
-        {
-            for uuid in &uuid_v {
-                let edge = self.edge_mp.remove(uuid).unwrap();
-                self.inx_code_target
-                    .get_mut(&((edge.paper, edge.code), edge.target))
-                    .unwrap()
-                    .remove(uuid);
-                if let Some(uuid_v) = self.inx_paper.get_mut(paper) {
-                    uuid_v.remove(uuid);
-                }
+    pub fn delete_edge(&mut self, source: &str, paper: &str, code: &str, target: &str) {
+        let (k, target) = match (
+            self.interner.get(source),
+            self.interner.get(paper),
+            self.interner.get(code),
+            self.interner.get(target),
+        ) {
+            (Some(source), Some(paper), Some(code), Some(target)) => {
+                ((source, (paper, code)), target)
             }
+            _ => return,
+        };
+        let uuid_v: Vec<u64> = match self.inx_source_code.get(&k) {
+            Some(uuid_v) => uuid_v
+                .iter()
+                .filter(|uuid| self.edge_mp[uuid].target == target)
+                .cloned()
+                .collect(),
+            None => return,
+        };
+        for uuid in uuid_v {
+            self.remove_edge(uuid);
+        }
+    }
+
+    /// Edges from or to `node`, in inserted order.
+    pub fn get_edge_v_of_node(&self, node: &str) -> Vec<Edge> {
+        self.get_uuid_set_of_node(node)
+            .iter()
+            .map(|uuid| self.to_edge(&self.edge_mp[uuid]))
+            .collect()
+    }
+
+    pub fn delete_edge_with_node(&mut self, node: &str) {
+        for uuid in self.get_uuid_set_of_node(node) {
+            self.remove_edge(uuid);
+        }
+    }
+
+    /// Edges from `node` by `inx_source_code` and to it by `inx_target_paper`, in inserted order.
+    fn get_uuid_set_of_node(&self, node: &str) -> BTreeSet<u64> {
+        let node = match self.interner.get(node) {
+            Some(node) => node,
+            None => return BTreeSet::new(),
+        };
+        let out_iter = self
+            .inx_source_code
+            .range((node, (Sym::MIN, Sym::MIN))..=(node, (Sym::MAX, Sym::MAX)));
+        let in_iter = self
+            .inx_target_paper
+            .range((node, Sym::MIN)..=(node, Sym::MAX));
+        out_iter
+            .flat_map(|(_, uuid_v)| uuid_v.iter().cloned())
+            .chain(in_iter.flat_map(|(_, uuid_v)| uuid_v.iter().cloned()))
+            .collect()
+    }
+
+    fn remove_edge(&mut self, uuid: u64) {
+        let edge = self.edge_mp.remove(&uuid).unwrap();
+        remove_uuid(
+            &mut self.inx_source_code,
+            &(edge.source, (edge.paper, edge.code)),
+            uuid,
+        );
+        remove_uuid(
+            &mut self.inx_code_target,
+            &((edge.paper, edge.code), edge.target),
+            uuid,
+        );
+        remove_uuid(&mut self.inx_target_paper, &(edge.target, edge.paper), uuid);
+        remove_uuid(&mut self.inx_paper, &edge.paper, uuid);
+        for sym in [edge.source, edge.paper, edge.code, edge.target] {
+            self.interner.release(sym);
         }
     }
 
     pub fn clear_paper(&mut self, paper: &str) {
-        if let Some(uuid_v) = self.inx_paper.remove(paper) {
-            for uuid in &uuid_v {
-                let edge = self.edge_mp.remove(uuid).unwrap();
-                self.inx_source_code
-                    .get_mut(&(edge.source.clone(), (edge.paper.clone(), edge.code.clone())))
-                    .unwrap()
-                    .remove(uuid);
-                self.inx_code_target
-                    .get_mut(&((edge.paper, edge.code), edge.target))
-                    .unwrap()
-                    .remove(uuid);
-            }
+        let uuid_v = match self.interner.get(paper) {
+            Some(paper) => self.inx_paper.get(&paper).cloned(),
+            None => None,
+        };
+        for uuid in uuid_v.unwrap_or_default() {
+            self.remove_edge(uuid);
         }
     }
 
     pub fn clear(&mut self) {
-        self.id = 0;
-        self.edge_mp.clear();
-        self.inx_source_code.clear();
-        self.inx_code_target.clear();
-        self.inx_paper.clear();
+        *self = Self::new();
     }
 
+    /// # Distinct codes from `root` in `space`, in inserted order of their first edges.
+    ///
+    /// `(root, space)` is a prefix of the keys of `inx_source_code`, so only the codes are
+    /// visited.
     pub fn get_code_v(&self, root: &str, space: &str) -> Vec<String> {
-        if let Some(id_v) = self.inx_paper.get(space) {
-            return id_v
-                .iter()
-                .filter(|id| {
-                    let edge = &self.edge_mp[id];
-                    edge.source == root
-                })
-                .map(|id| {
-                    let edge = &self.edge_mp[id];
-                    edge.code.clone()
-                })
-                .collect();
-        }
-        Vec::new()
+        let (root, space) = match (self.interner.get(root), self.interner.get(space)) {
+            (Some(root), Some(space)) => (root, space),
+            _ => return Vec::new(),
+        };
+        let mut code_v: Vec<(u64, Sym)> = self
+            .inx_source_code
+            .range((root, (space, Sym::MIN))..=(root, (space, Sym::MAX)))
+            .filter_map(|((_, (_, code)), uuid_v)| uuid_v.get_min().map(|uuid| (*uuid, *code)))
+            .collect();
+        code_v.sort_unstable();
+        code_v
+            .into_iter()
+            .map(|(_, code)| self.interner.resolve(code))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::MemTable;
+
+    fn new_table() -> MemTable {
+        let mut table = MemTable::new();
+        for (source, code, target) in [
+            ("a", "x", "b"),
+            ("b", "x", "c"),
+            ("c", "y", "a"),
+            ("d", "x", "e"),
+            ("b", "y", "b"),
+        ] {
+            table.insert_edge(source, "p", code, target).unwrap();
+        }
+        table
+    }
+
+    #[test]
+    fn should_get_edge_v_of_node() {
+        let table = new_table();
+        let edge_v: Vec<(String, String)> = table
+            .get_edge_v_of_node("b")
+            .into_iter()
+            .map(|edge| (edge.source, edge.target))
+            .collect();
+        assert_eq!(
+            edge_v,
+            [
+                ("a".to_string(), "b".to_string()),
+                ("b".to_string(), "c".to_string()),
+                ("b".to_string(), "b".to_string())
+            ]
+        );
+        assert!(table.get_edge_v_of_node("z").is_empty());
+    }
+
+    #[test]
+    fn should_delete_edge_with_node() {
+        let mut table = new_table();
+        table.delete_edge_with_node("b");
+        assert!(table.get_edge_v_of_node("b").is_empty());
+        assert!(table.get_target_v("a", "p", "x").is_empty());
+        assert!(table.get_source_v("p", "x", "c").is_empty());
+        assert_eq!(table.get_target_v("c", "p", "y"), ["a"]);
+        assert_eq!(table.get_source_v_of_paper("p", "e"), ["d"]);
+    }
+
+    #[test]
+    fn should_delete_edge() {
+        let mut table = new_table();
+        table.insert_edge("a", "p", "x", "c").unwrap();
+        table.delete_edge("a", "p", "x", "b");
+        assert_eq!(table.get_target_v("a", "p", "x"), ["c"]);
+        table.delete_edge_with_code_target("p", "x", "c");
+        assert!(table.get_target_v("a", "p", "x").is_empty());
+        assert!(table.get_source_v_of_paper("p", "c").is_empty());
+        assert_eq!(table.get_code_v("b", "p"), ["y"]);
+    }
+
+    #[test]
+    fn should_release_string() {
+        let mut table = new_table();
+        let sym_cnt = table.interner.entry_v.len();
+        for node in ["a", "b", "c", "d", "e"] {
+            table.delete_edge_with_node(node);
+        }
+        assert!(table.interner.sym_mp.is_empty());
+
+        // the ids are taken again
+        table.insert_edge("f", "p", "x", "g").unwrap();
+        assert_eq!(table.interner.entry_v.len(), sym_cnt);
+        assert_eq!(table.get_target_v("f", "p", "x"), ["g"]);
+        assert!(table.get_target_v("a", "p", "x").is_empty());
+    }
+
+    #[test]
+    fn should_reject_corrupted_snapshot() {
+        for content in [
+            "edge-mem-table 2 0\n2\n0\ta\tp\tx\tb\n0\ta\tp\tx\tc\n",
+            "edge-mem-table 2 0\n2\n2\ta\tp\tx\tb\n",
+            "edge-mem-table 2 0\n2\n1\ta\tp\tx\n",
+            "edge-mem-table 2 0\nx\n",
+        ] {
+            let e = MemTable::load_from(content.as_bytes()).err().unwrap();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{content}");
+        }
+    }
+
+    #[test]
+    fn should_load_generation() {
+        let mut buf = Vec::new();
+        new_table().save_to(&mut buf, 7).unwrap();
+        let (table, generation) = MemTable::load_from(&buf[..]).unwrap();
+        assert_eq!(generation, 7);
+        assert_eq!(table.get_target_v("b", "p", "y"), ["b"]);
+
+        // the next id of version 1 was the base of its log
+        let (_, generation) = MemTable::load_from("edge-mem-table 1\n3\n".as_bytes()).unwrap();
+        assert_eq!(generation, 3);
+        assert!(MemTable::load_from("edge-mem-table 2\n3\n".as_bytes()).is_err());
     }
 }